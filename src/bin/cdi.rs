@@ -0,0 +1,370 @@
+//! Inspects and extracts CD-i real-time files and disc images.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use og_lib_cdi::data::cdi_disc::CdiDisc;
+use og_lib_cdi::data::cdi_file::CdiFile;
+use og_lib_cdi::data::cdi_sector::CdiSector;
+use og_lib_cdi::data::CdiSectorType;
+use og_lib_cdi::helpers::audio_helpers::{decode_audio_sectors, write_wav};
+use og_lib_cdi::helpers::color_helpers::{
+    read_act_palette, read_gpl_palette, read_hex_palette, read_jasc_palette, write_act_palette, write_gpl_palette,
+    write_hex_palette, write_jasc_palette, write_palette,
+};
+use og_lib_cdi::helpers::extraction_helpers::{extract_all_images, ExtractOptions};
+use og_lib_cdi::helpers::mpeg_helpers::{demux_mpeg, write_mpeg_streams};
+use og_lib_cdi::helpers::palette_discovery_helpers::find_palette_candidates;
+
+/// The size of a raw sector, which files given to the commands are made of.
+const SECTOR_SIZE: usize = 2352;
+
+const USAGE: &str = "usage: cdi <command> [arguments] [options]
+
+commands:
+  info <file>                          sector statistics by type, channel and coding
+  sectors <file>                       one line per sector with its subheader
+  extract-audio <file> <output.wav>    decode the ADPCM audio of a channel
+  extract-images <file> <output dir>   decode every video record to numbered PNGs
+  extract-mpeg <file> <output base>    write .m1v, .mp2 and .mpg streams
+  palette <file>                       list palettes found in data sectors
+  disc ls <image>                      list the files of a disc image
+  disc extract <image> <path> <output> write the raw sectors of a file on a disc image
+
+options:
+  --channel <n>            only use sectors of this channel
+  --width <n>              image width for extract-images, guessed when missing
+  --height <n>             image height for extract-images, guessed when missing
+  --palette <file>         .act, .gpl, .pal or .hex palette used before one is found
+  --prefix <name>          image file prefix for extract-images
+  --min-confidence <x>     palette detection threshold, 0.5 by default
+  --export <file>          write the most confident palette as .act, .gpl, .pal, .hex or .png";
+
+/// Positional arguments and `--name value` options.
+struct Arguments {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Arguments {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args.next().ok_or(format!("--{} needs a value", name))?;
+                    options.insert(name.to_string(), value.clone());
+                }
+                None => positional.push(arg.clone()),
+            }
+        }
+        Ok(Arguments { positional, options })
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(|s| s.as_str())
+            .ok_or(format!("missing {}\n\n{}", name, USAGE))
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.options.get(name) {
+            Some(value) => value.parse().map(Some).map_err(|_| format!("invalid value for --{}: {}", name, value)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let arguments = Arguments::parse(args)?;
+    match arguments.positional.first().map(|s| s.as_str()) {
+        Some("info") => info(&open_file(arguments.positional(1, "file")?)?),
+        Some("sectors") => sectors(&open_file(arguments.positional(1, "file")?)?),
+        Some("extract-audio") => extract_audio(&arguments),
+        Some("extract-images") => extract_images(&arguments),
+        Some("extract-mpeg") => extract_mpeg(&arguments),
+        Some("palette") => palette(&arguments),
+        Some("disc") => disc(&arguments),
+        Some("help") | None => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command {}\n\n{}", command, USAGE).into()),
+    }
+}
+
+/// Reads a file of raw sectors, ignoring a partial sector at its end.
+fn open_file(path: &str) -> Result<CdiFile, Box<dyn std::error::Error>> {
+    let mut data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    if data.len() < SECTOR_SIZE {
+        return Err(format!("{}: {} bytes is shorter than one {} byte sector", path, data.len(), SECTOR_SIZE).into());
+    }
+    let partial_bytes = data.len() % SECTOR_SIZE;
+    if partial_bytes != 0 {
+        eprintln!("warning: ignoring the last {} bytes of {}, which are not a whole sector", partial_bytes, path);
+        data.truncate(data.len() - partial_bytes);
+    }
+    Ok(CdiFile::from_data(path.to_string(), data))
+}
+
+fn sector_type_name(sector: &CdiSector) -> &'static str {
+    match sector.get_sector_type() {
+        CdiSectorType::Video => "video",
+        CdiSectorType::Audio => "audio",
+        CdiSectorType::Data => "data",
+        CdiSectorType::Message => "message",
+        CdiSectorType::Empty => "empty",
+    }
+}
+
+fn coding_description(sector: &CdiSector) -> String {
+    let coding_info = sector.coding_info();
+    match sector.get_sector_type() {
+        CdiSectorType::Video => format!("{} {}", coding_info.video_string(), coding_info.resolution_string()),
+        CdiSectorType::Audio => format!(
+            "{} {} {}",
+            coding_info.bits_per_sample_string(),
+            coding_info.sample_rate_string(),
+            if coding_info.is_stereo() { "stereo" } else { "mono" }
+        ),
+        _ => "-".to_string(),
+    }
+}
+
+fn submode_flags(sector: &CdiSector) -> String {
+    let submode = sector.submode();
+    let flags = [
+        (submode.is_eof(), "EOF"),
+        (submode.is_rtf(), "RT"),
+        (submode.is_form2(), "F2"),
+        (submode.is_trigger(), "T"),
+        (submode.is_data(), "D"),
+        (submode.is_audio(), "A"),
+        (submode.is_video(), "V"),
+        (submode.is_eor(), "EOR"),
+    ];
+    let set: Vec<&str> = flags.iter().filter(|(set, _)| *set).map(|(_, name)| *name).collect();
+    if set.is_empty() {
+        "-".to_string()
+    } else {
+        set.join("|")
+    }
+}
+
+fn info(file: &CdiFile) -> Result<(), Box<dyn std::error::Error>> {
+    println!("file: {}", file.file_name());
+    println!("size: {} bytes", file.size());
+    println!("sectors: {}", file.sector_count());
+
+    let mut types: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut channels: BTreeMap<u8, BTreeMap<&str, usize>> = BTreeMap::new();
+    let mut codings: BTreeMap<(&str, String), usize> = BTreeMap::new();
+    for sector in file.sectors() {
+        let sector_type = sector_type_name(sector);
+        *types.entry(sector_type).or_default() += 1;
+        *channels.entry(sector.channel_number()).or_default().entry(sector_type).or_default() += 1;
+        *codings.entry((sector_type, coding_description(sector))).or_default() += 1;
+    }
+
+    println!("\nsector types:");
+    for (sector_type, count) in &types {
+        println!("  {:<8} {}", sector_type, count);
+    }
+    println!("\nchannels:");
+    for (channel, counts) in &channels {
+        let counts: Vec<String> = counts.iter().map(|(t, c)| format!("{} {}", c, t)).collect();
+        println!("  {:<3} {}", channel, counts.join(", "));
+    }
+    println!("\ncodings:");
+    for ((sector_type, coding), count) in &codings {
+        println!("  {:<8} {:<28} {}", sector_type, coding, count);
+    }
+    Ok(())
+}
+
+fn sectors(file: &CdiFile) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{:>7} {:>4} {:>7} {:<20} {:<7} coding", "sector", "file", "channel", "submode", "type");
+    for sector in file.sectors() {
+        println!(
+            "{:>7} {:>4} {:>7} {:<20} {:<7} {}",
+            sector.sector_index(),
+            sector.file_number(),
+            sector.channel_number(),
+            submode_flags(sector),
+            sector_type_name(sector),
+            coding_description(sector)
+        );
+    }
+    Ok(())
+}
+
+fn extract_audio(arguments: &Arguments) -> Result<(), Box<dyn std::error::Error>> {
+    let file = open_file(arguments.positional(1, "file")?)?;
+    let output = arguments.positional(2, "output file")?;
+    let audio_sectors = file.get_audio_sectors();
+    let channel = match arguments.number::<u8>("channel")? {
+        Some(channel) => channel,
+        None => audio_sectors.first().ok_or("the file holds no audio sectors")?.channel_number(),
+    };
+    let channel_sectors: Vec<&CdiSector> = audio_sectors.into_iter().filter(|s| s.channel_number() == channel).collect();
+    if channel_sectors.is_empty() {
+        return Err(format!("channel {} holds no audio sectors", channel).into());
+    }
+
+    let audio = decode_audio_sectors(&channel_sectors);
+    write_wav(output, &audio)?;
+    let frames = audio.samples.len() / audio.channels as usize;
+    println!(
+        "wrote {} sectors of channel {} to {}: {} Hz, {} channels, {:.2} seconds",
+        channel_sectors.len(),
+        channel,
+        output,
+        audio.sample_rate,
+        audio.channels,
+        frames as f64 / audio.sample_rate as f64
+    );
+    Ok(())
+}
+
+fn read_palette_file(path: &str) -> Result<Vec<image::Rgba<u8>>, Box<dyn std::error::Error>> {
+    match extension(path).as_str() {
+        "act" => read_act_palette(path),
+        "gpl" => read_gpl_palette(path),
+        "pal" => read_jasc_palette(path),
+        "hex" | "txt" => read_hex_palette(path),
+        _ => Err(format!("unknown palette format: {}", path).into()),
+    }
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+fn extract_images(arguments: &Arguments) -> Result<(), Box<dyn std::error::Error>> {
+    let file = open_file(arguments.positional(1, "file")?)?;
+    let mut options = ExtractOptions::new(arguments.positional(2, "output directory")?);
+    options.channel = arguments.number("channel")?;
+    options.context.width = arguments.number("width")?.unwrap_or(0);
+    options.context.height = arguments.number("height")?.unwrap_or(0);
+    if let Some(confidence) = arguments.number("min-confidence")? {
+        options.min_palette_confidence = confidence;
+    }
+    if let Some(prefix) = arguments.options.get("prefix") {
+        options.file_prefix = prefix.clone();
+    }
+    if let Some(path) = arguments.options.get("palette") {
+        options.context.clut = read_palette_file(path)?;
+    }
+
+    let records = extract_all_images(&file, &options)?;
+    let saved = records.iter().filter(|r| r.result.is_ok()).count();
+    for record in &records {
+        if let Err(error) = &record.result {
+            eprintln!("sector {}: {}", record.first_sector, error);
+        }
+    }
+    println!("saved {} of {} records to {}", saved, records.len(), options.output_dir);
+    Ok(())
+}
+
+fn extract_mpeg(arguments: &Arguments) -> Result<(), Box<dyn std::error::Error>> {
+    let file = open_file(arguments.positional(1, "file")?)?;
+    let result = demux_mpeg(&file, arguments.number("channel")?);
+    if result.video_sectors + result.audio_sectors == 0 {
+        return Err("the file holds no MPEG sectors".into());
+    }
+    for gap in &result.gaps {
+        eprintln!("gap: {} sectors missing after sector {}", gap.missing_sectors, gap.after_sector_index);
+    }
+    for path in write_mpeg_streams(&result, arguments.positional(2, "output base path")?)? {
+        println!("wrote {}", path);
+    }
+    println!("{} video sectors, {} audio sectors, {} gaps", result.video_sectors, result.audio_sectors, result.gaps.len());
+    Ok(())
+}
+
+fn palette(arguments: &Arguments) -> Result<(), Box<dyn std::error::Error>> {
+    let file = open_file(arguments.positional(1, "file")?)?;
+    let min_confidence = arguments.number("min-confidence")?.unwrap_or(0.5);
+    let candidates = find_palette_candidates(&file, min_confidence);
+    if candidates.is_empty() {
+        return Err("no palettes were found".into());
+    }
+    println!("{:>7} {:>6} {:<8} {:>6} {:>5} confidence", "sector", "offset", "type", "colors", "start");
+    for candidate in &candidates {
+        println!(
+            "{:>7} {:>6} {:<8} {:>6} {:>5} {:.2}",
+            candidate.sector_index,
+            candidate.offset,
+            format!("{:?}", candidate.palette_type),
+            candidate.colors.len(),
+            candidate.start_index,
+            candidate.confidence
+        );
+    }
+
+    if let Some(path) = arguments.options.get("export") {
+        let best = candidates
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+            .ok_or("no palettes were found")?;
+        match extension(path).as_str() {
+            "act" => write_act_palette(path, &best.colors)?,
+            "gpl" => write_gpl_palette(path, &best.colors)?,
+            "pal" => write_jasc_palette(path, &best.colors)?,
+            "hex" | "txt" => write_hex_palette(path, &best.colors)?,
+            "png" => write_palette(path, &best.colors)?,
+            _ => return Err(format!("unknown palette format: {}", path).into()),
+        }
+        println!("wrote the palette of sector {} to {}", best.sector_index, path);
+    }
+    Ok(())
+}
+
+fn disc(arguments: &Arguments) -> Result<(), Box<dyn std::error::Error>> {
+    let subcommand = arguments.positional(1, "disc command")?;
+    let disc = CdiDisc::from_file(open_file(arguments.positional(2, "disc image")?)?)?;
+    match subcommand {
+        "ls" => {
+            let label = disc.label();
+            println!("{} ({})", label.volume_identifier, label.system_identifier);
+            for entry in disc.entries()? {
+                if entry.is_directory {
+                    println!("{:>10} {:>8} {}/", "-", entry.start_block, entry.path);
+                } else {
+                    println!("{:>10} {:>8} {}", entry.size, entry.start_block, entry.path);
+                }
+            }
+            Ok(())
+        }
+        "extract" => {
+            let path = arguments.positional(3, "file path")?;
+            let output = arguments.positional(4, "output file")?;
+            let entry = disc.find(path)?.ok_or(format!("{} was not found", path))?;
+            let sector_count = disc.extract_file(&entry, output)?;
+            println!("wrote {} sectors of {} to {}", sector_count, entry.path, output);
+            Ok(())
+        }
+        _ => Err(format!("unknown disc command {}\n\n{}", subcommand, USAGE).into()),
+    }
+}
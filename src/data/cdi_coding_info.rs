@@ -1,3 +1,5 @@
+use crate::data::CdiResolution;
+
 #[derive(Clone, Copy)]
 pub struct CdiCodingInfo {
   byte: u8,
@@ -84,6 +86,15 @@ impl CdiCodingInfo {
     }
   }
 
+  pub fn resolution_type(&self) -> CdiResolution {
+    match self.resolution() {
+      0 => CdiResolution::Normal,
+      1 => CdiResolution::Double,
+      3 => CdiResolution::High,
+      _ => CdiResolution::Reserved,
+    }
+  }
+
   pub fn coding(&self) -> u8 {
     self.byte & 0b1111
   }
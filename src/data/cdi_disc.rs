@@ -0,0 +1,228 @@
+use crate::data::cdi_file::CdiFile;
+use crate::data::cdi_sector::CdiSector;
+use std::fs;
+
+/// The volume description found in the disc label of a CD-i disc.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CdiDiscLabel {
+    pub system_identifier: String,
+    pub volume_identifier: String,
+    pub volume_space_size: u32,
+    pub path_table_size: u32,
+    pub path_table_address: u32,
+}
+
+/// A file or directory listed in the directories of a disc.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CdiDirectoryEntry {
+    /// The full path from the root directory, separated by `/`.
+    pub path: String,
+    pub start_block: u32,
+    pub size: u32,
+    pub is_directory: bool,
+    pub attributes: u16,
+    /// The subheader file number of the sectors of a real-time file, 0 for ordinary files.
+    pub file_number: u8,
+}
+
+/// A raw 2352-byte sector image of a CD-i disc, read through its CD-RTOS file structure.
+pub struct CdiDisc {
+    file: CdiFile,
+    /// The logical block number of the first sector of the image.
+    first_block: u32,
+    label: CdiDiscLabel,
+}
+
+impl CdiDisc {
+    const BLOCK_SIZE: usize = 2048;
+    /// The disc label is searched for from this block onwards.
+    const DISC_LABEL_BLOCK: u32 = 16;
+    /// Logical block 0 is at address 00:02:00.
+    const PREGAP_SECTORS: u32 = 150;
+    const DIRECTORY_ATTRIBUTE: u16 = 0x8000;
+    const DIRECTORY_FLAG: u8 = 0x02;
+
+    pub fn new(file_name: String) -> Result<Self, Box<dyn std::error::Error>> {
+        fs::metadata(&file_name)?;
+        Self::from_file(CdiFile::new(file_name))
+    }
+
+    /// Reads the disc label of an image, numbering blocks from the header address of the
+    /// first sector when it has one.
+    pub fn from_file(file: CdiFile) -> Result<Self, Box<dyn std::error::Error>> {
+        if file.sector_count() == 0 {
+            return Err("the disc image is empty".into());
+        }
+        let first_block = file
+            .sector(0)
+            .header_address()
+            .map(|address| address.saturating_sub(Self::PREGAP_SECTORS))
+            .unwrap_or(0);
+        let mut disc = CdiDisc {
+            file,
+            first_block,
+            label: CdiDiscLabel {
+                system_identifier: String::new(),
+                volume_identifier: String::new(),
+                volume_space_size: 0,
+                path_table_size: 0,
+                path_table_address: 0,
+            },
+        };
+
+        let label = (Self::DISC_LABEL_BLOCK..Self::DISC_LABEL_BLOCK + 16)
+            .filter_map(|block| disc.block(block))
+            .find(|data| data.len() >= 152 && data[0] == 1 && &data[1..6] == b"CD-I ")
+            .ok_or("no CD-I disc label was found")?;
+        disc.label = CdiDiscLabel {
+            system_identifier: read_string(&label[8..40]),
+            volume_identifier: read_string(&label[40..72]),
+            volume_space_size: read_u32(&label, 84),
+            path_table_size: read_u32(&label, 136),
+            path_table_address: read_u32(&label, 148),
+        };
+        Ok(disc)
+    }
+
+    pub fn label(&self) -> &CdiDiscLabel {
+        &self.label
+    }
+
+    pub fn file(&self) -> &CdiFile {
+        &self.file
+    }
+
+    /// Returns the sector holding a logical block.
+    pub fn sector(&self, block: u32) -> Option<&CdiSector> {
+        let index = block.checked_sub(self.first_block)? as usize;
+        self.file.sectors().get(index)
+    }
+
+    /// Returns the 2048 bytes of form 1 data of a logical block.
+    fn block(&self, block: u32) -> Option<Vec<u8>> {
+        let sector = self.sector(block)?;
+        Some(sector.sector_data.iter().skip(24).take(Self::BLOCK_SIZE).cloned().collect())
+    }
+
+    /// Lists every file and directory on the disc, directories before their contents.
+    ///
+    /// The root directory is taken from the first entry of the path table and directories
+    /// are then followed through their records.
+    pub fn entries(&self) -> Result<Vec<CdiDirectoryEntry>, Box<dyn std::error::Error>> {
+        let path_table = self.block(self.label.path_table_address).ok_or("the path table is outside the image")?;
+        if path_table.len() < 6 || path_table[0] == 0 {
+            return Err("the path table is empty".into());
+        }
+        let root_block = read_u32(&path_table, 2);
+
+        let mut entries = Vec::new();
+        let mut visited = vec![root_block];
+        self.read_directory(root_block, "", &mut entries, &mut visited)?;
+        Ok(entries)
+    }
+
+    fn read_directory(
+        &self,
+        block: u32,
+        parent_path: &str,
+        entries: &mut Vec<CdiDirectoryEntry>,
+        visited: &mut Vec<u32>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let first_block = self.block(block).ok_or("a directory is outside the image")?;
+        // The first record describes the directory itself
+        let directory_size = if first_block[0] >= 18 { read_u32(&first_block, 14) } else { 0 };
+        let block_count = (directory_size as usize).div_ceil(Self::BLOCK_SIZE).max(1) as u32;
+
+        for block_offset in 0..block_count {
+            let Some(data) = self.block(block + block_offset) else {
+                break;
+            };
+            let mut position = 0;
+            while position < data.len() && data[position] != 0 {
+                let length = data[position] as usize;
+                let record = &data[position..(position + length).min(data.len())];
+                position += length;
+                if record.len() < 34 {
+                    break;
+                }
+
+                let name_size = record[32] as usize;
+                let Some(name) = record.get(33..33 + name_size) else {
+                    break;
+                };
+                // The directory itself and its parent are named 0 and 1
+                if name == [0] || name == [1] {
+                    continue;
+                }
+                let system_use = 33 + name_size + (name_size + 1) % 2;
+                let attributes = record.get(system_use + 4..system_use + 6).map_or(0, |a| u16::from_be_bytes([a[0], a[1]]));
+                let file_number = record.get(system_use + 8).copied().unwrap_or(0);
+
+                let entry = CdiDirectoryEntry {
+                    path: format!("{}/{}", parent_path, String::from_utf8_lossy(name)),
+                    start_block: read_u32(record, 6),
+                    size: read_u32(record, 14),
+                    is_directory: record[25] & Self::DIRECTORY_FLAG != 0 || attributes & Self::DIRECTORY_ATTRIBUTE != 0,
+                    attributes,
+                    file_number,
+                };
+                entries.push(entry.clone());
+                if entry.is_directory && !visited.contains(&entry.start_block) {
+                    visited.push(entry.start_block);
+                    self.read_directory(entry.start_block, &entry.path, entries, visited)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds an entry by its path, ignoring case and a leading `/`.
+    pub fn find(&self, path: &str) -> Result<Option<CdiDirectoryEntry>, Box<dyn std::error::Error>> {
+        let path = format!("/{}", path.trim_start_matches('/'));
+        Ok(self.entries()?.into_iter().find(|e| e.path.eq_ignore_ascii_case(&path)))
+    }
+
+    /// Returns the sectors of a file.
+    ///
+    /// Sectors are read from the start of the file until its size in blocks has been found.
+    /// For real-time files, which are interleaved with other files, only sectors with the
+    /// file's subheader file number count, and reading stops after its end of file sector.
+    pub fn file_sectors(&self, entry: &CdiDirectoryEntry) -> Vec<&CdiSector> {
+        let block_count = (entry.size as usize).div_ceil(Self::BLOCK_SIZE);
+        let mut sectors = Vec::new();
+        let mut block = entry.start_block;
+        while sectors.len() < block_count {
+            let Some(sector) = self.sector(block) else {
+                break;
+            };
+            block += 1;
+            if entry.file_number != 0 && sector.file_number() != entry.file_number {
+                continue;
+            }
+            sectors.push(sector);
+            if entry.file_number != 0 && sector.submode().is_eof() {
+                break;
+            }
+        }
+        sectors
+    }
+
+    /// Writes the raw sectors of a file, which can then be opened as a `CdiFile`.
+    pub fn extract_file(&self, entry: &CdiDirectoryEntry, path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        if entry.is_directory {
+            return Err(format!("{} is a directory", entry.path).into());
+        }
+        let sectors = self.file_sectors(entry);
+        let data: Vec<u8> = sectors.iter().flat_map(|s| s.sector_data.iter().cloned()).collect();
+        fs::write(path, data)?;
+        Ok(sectors.len())
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    data.get(offset..offset + 4).map_or(0, |b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_string(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).trim_end_matches([' ', '\0']).to_string()
+}
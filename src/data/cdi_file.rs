@@ -1,4 +1,6 @@
 use crate::data::cdi_sector::CdiSector;
+use crate::data::CdiVideoType;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
 
 pub struct CdiFile {
@@ -11,6 +13,11 @@ impl CdiFile {
   const SECTOR_SIZE: u64 = 2352;
     pub fn new(file_name: String) -> Self {
         let data = fs::read(&file_name).unwrap();
+        Self::from_data(file_name, data)
+    }
+
+    /// Splits data that has already been read into sectors, naming it `file_name`.
+    pub fn from_data(file_name: String, data: Vec<u8>) -> Self {
         let mut sectors = Vec::<CdiSector>::new();
         let mut parsed: usize = 0;
         let mut sector_count = 0;
@@ -62,4 +69,61 @@ impl CdiFile {
     pub fn get_data_sectors(&self) -> Vec<&CdiSector> {
         self.sectors.iter().filter(|s| s.submode_info.is_data()).collect()
     }
+
+    /// Groups video sectors into records, each ending with an end of record or trigger sector.
+    ///
+    /// Some discs only mark the end of each frame with the trigger bit, so either bit closes
+    /// the record. Records are tracked separately for each file and channel number, so interleaved
+    /// channels do not split each other's records. Records that are still open at the
+    /// end of the file are returned after the complete ones.
+    pub fn get_video_records(&self) -> Vec<Vec<&CdiSector>> {
+        let mut open_records: Vec<((u8, u8), Vec<&CdiSector>)> = Vec::new();
+        let mut records = Vec::new();
+
+        for sector in self.get_video_sectors() {
+            let key = (sector.file_number(), sector.channel_number());
+            let position = match open_records.iter().position(|(k, _)| *k == key) {
+                Some(position) => position,
+                None => {
+                    open_records.push((key, Vec::new()));
+                    open_records.len() - 1
+                }
+            };
+            open_records[position].1.push(sector);
+            if sector.submode().is_eor() || sector.submode().is_trigger() {
+                records.push(open_records.remove(position).1);
+            }
+        }
+
+        records.extend(open_records.into_iter().map(|(_, record)| record));
+        records
+    }
+
+    /// Pairs RGB555L sectors with their RGB555H counterparts.
+    ///
+    /// Sectors are matched in order of appearance within each file and channel number, so
+    /// the nth lower sector of a channel is paired with the nth upper sector of that channel.
+    pub fn get_rgb555_sector_pairs(&self) -> Vec<(&CdiSector, &CdiSector)> {
+        let mut pending_lower: HashMap<(u8, u8), VecDeque<&CdiSector>> = HashMap::new();
+        let mut pending_upper: HashMap<(u8, u8), VecDeque<&CdiSector>> = HashMap::new();
+        let mut pairs = Vec::new();
+
+        for sector in self.get_video_sectors() {
+            let key = (sector.file_number(), sector.channel_number());
+            let coding = sector.coding_info().coding();
+            if coding == CdiVideoType::RGB555L as u8 {
+                match pending_upper.get_mut(&key).and_then(|q| q.pop_front()) {
+                    Some(upper) => pairs.push((sector, upper)),
+                    None => pending_lower.entry(key).or_default().push_back(sector),
+                }
+            } else if coding == CdiVideoType::RGB555H as u8 {
+                match pending_lower.get_mut(&key).and_then(|q| q.pop_front()) {
+                    Some(lower) => pairs.push((lower, sector)),
+                    None => pending_upper.entry(key).or_default().push_back(sector),
+                }
+            }
+        }
+
+        pairs
+    }
 }
@@ -37,6 +37,25 @@ impl CdiSector {
     self.sector_index
   }
 
+  /// Returns the sector number held in the minute, second and frame address of the header,
+  /// or `None` when the address is not valid BCD.
+  pub fn header_address(&self) -> Option<u32> {
+    let from_bcd = |b: u8| if b >> 4 < 10 && b & 0x0F < 10 { Some((b >> 4) as u32 * 10 + (b & 0x0F) as u32) } else { None };
+    let minute = from_bcd(*self.sector_data.get(12)?)?;
+    let second = from_bcd(*self.sector_data.get(13)?)?;
+    let frame = from_bcd(*self.sector_data.get(14)?)?;
+    if second >= 60 || frame >= 75 {
+      return None;
+    }
+    Some((minute * 60 + second) * 75 + frame)
+  }
+
+  /// Returns the whole form 2 data area, which MPEG sectors fill regardless of their type.
+  pub fn get_form2_data(&self) -> Vec<u8> {
+    let start_offset = (Self::HEADER_SIZE + Self::SUBHEADER_SIZE) as usize;
+    self.sector_data.iter().skip(start_offset).take(Self::SECTOR_VIDEO_SIZE as usize).cloned().collect()
+  }
+
   pub fn file_number(&self) -> u8 {
     self.sector_sub_header_data[CdiSubHeaderByte::FileNumber as usize]
   }
@@ -3,7 +3,9 @@ pub mod cdi_file;
 pub mod cdi_sector;
 pub mod cdi_coding_info;
 pub mod cdi_submode_info;
+pub mod cdi_disc;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CdiPaletteType {
     RGB,
     Indexed,
@@ -32,6 +34,14 @@ pub enum CdiVideoType {
     Reserved,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CdiResolution {
+    Normal,
+    Double,
+    Reserved,
+    High,
+}
+
 pub enum CdiSubHeaderByte {
     FileNumber,
     ChannelNumber,
@@ -0,0 +1,265 @@
+use std::{borrow::Cow, fs::File, io::BufWriter, io::Write, path::Path};
+
+use gif::{Encoder, Frame, Repeat};
+use image::{imageops::FilterType, ImageBuffer, Rgba};
+use image_webp::{ColorType, WebPEncoder};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    WebP,
+}
+
+impl AnimationFormat {
+    /// Picks the format from a file extension, `.png` and `.apng` being APNG.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            "webp" => Some(AnimationFormat::WebP),
+            _ => None,
+        }
+    }
+}
+
+pub struct AnimationFrame {
+    pub image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    /// How long the frame is shown, in milliseconds.
+    pub delay_ms: u32,
+}
+
+/// The canvas size and looping of an animation.
+///
+/// A `loop_count` of 0 loops forever, otherwise it is the number of times the animation is played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnimationConfig {
+    pub format: AnimationFormat,
+    pub width: u32,
+    pub height: u32,
+    pub loop_count: u32,
+}
+
+/// Writes the frames of an animation to a file.
+///
+/// Frames whose dimensions differ from the canvas are resized with nearest neighbour
+/// filtering, other frames are written untouched.
+pub trait AnimationWriter {
+    fn write_frame(&mut self, frame: &AnimationFrame) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Completes the file, which is only valid once this has been called.
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Creates the writer for `config.format` at `path`.
+pub fn create_animation_writer(path: &str, config: AnimationConfig) -> Result<Box<dyn AnimationWriter>, Box<dyn std::error::Error>> {
+    Ok(match config.format {
+        AnimationFormat::Gif => Box::new(GifWriter::new(path, config)?),
+        AnimationFormat::Apng => Box::new(ApngWriter::new(path, config)?),
+        AnimationFormat::WebP => Box::new(WebPWriter::new(path, config)?),
+    })
+}
+
+/// Writes all frames to an animation file.
+pub fn write_animation(path: &str, frames: &[AnimationFrame], config: AnimationConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = create_animation_writer(path, config)?;
+    for frame in frames {
+        writer.write_frame(frame)?;
+    }
+    writer.finish()
+}
+
+fn fit_to_canvas(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, width: u32, height: u32) -> Cow<'_, ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    if image.dimensions() == (width, height) {
+        Cow::Borrowed(image)
+    } else {
+        Cow::Owned(image::imageops::resize(image, width, height, FilterType::Nearest))
+    }
+}
+
+fn check_canvas(config: &AnimationConfig, max_size: u32) -> Result<(), Box<dyn std::error::Error>> {
+    if config.width == 0 || config.height == 0 || config.width > max_size || config.height > max_size {
+        return Err(format!("invalid animation size {}x{}", config.width, config.height).into());
+    }
+    Ok(())
+}
+
+/// Writes GIF animations, quantising each frame to its own 256 colour palette.
+///
+/// GIF delays are in hundredths of a second, so frame delays are rounded to the nearest 10ms.
+pub struct GifWriter {
+    encoder: Encoder<BufWriter<File>>,
+    width: u16,
+    height: u16,
+}
+
+impl GifWriter {
+    pub fn new(path: &str, config: AnimationConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        check_canvas(&config, u16::MAX as u32)?;
+        let (width, height) = (config.width as u16, config.height as u16);
+        let mut encoder = Encoder::new(BufWriter::new(File::create(path)?), width, height, &[])?;
+        // Without a repeat extension the animation plays once
+        match config.loop_count {
+            0 => encoder.set_repeat(Repeat::Infinite)?,
+            1 => {}
+            plays => encoder.set_repeat(Repeat::Finite((plays - 1).min(u16::MAX as u32) as u16))?,
+        }
+        Ok(GifWriter { encoder, width, height })
+    }
+}
+
+impl AnimationWriter for GifWriter {
+    fn write_frame(&mut self, frame: &AnimationFrame) -> Result<(), Box<dyn std::error::Error>> {
+        let image = fit_to_canvas(&frame.image, self.width as u32, self.height as u32);
+        let mut pixels = image.as_raw().clone();
+        let mut gif_frame = Frame::from_rgba_speed(self.width, self.height, &mut pixels, 10);
+        gif_frame.delay = ((frame.delay_ms + 5) / 10).min(u16::MAX as u32) as u16;
+        self.encoder.write_frame(&gif_frame)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+        self.encoder.into_inner()?.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes APNG animations with full 8-bit RGBA colour.
+///
+/// The frame count is part of the APNG header, so frames are held until `finish`.
+pub struct ApngWriter {
+    file: File,
+    config: AnimationConfig,
+    frames: Vec<(Vec<u8>, u32)>,
+}
+
+impl ApngWriter {
+    pub fn new(path: &str, config: AnimationConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        check_canvas(&config, i32::MAX as u32)?;
+        Ok(ApngWriter {
+            file: File::create(path)?,
+            config,
+            frames: Vec::new(),
+        })
+    }
+}
+
+impl AnimationWriter for ApngWriter {
+    fn write_frame(&mut self, frame: &AnimationFrame) -> Result<(), Box<dyn std::error::Error>> {
+        let image = fit_to_canvas(&frame.image, self.config.width, self.config.height);
+        self.frames.push((image.into_owned().into_raw(), frame.delay_ms));
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+        if self.frames.is_empty() {
+            return Err("an animation needs at least one frame".into());
+        }
+        let mut encoder = png::Encoder::new(BufWriter::new(self.file), self.config.width, self.config.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.frames.len() as u32, self.config.loop_count)?;
+        let mut writer = encoder.write_header()?;
+        for (pixels, delay_ms) in &self.frames {
+            // Delays are a fraction of a second, fall back to hundredths when milliseconds overflow
+            if *delay_ms <= u16::MAX as u32 {
+                writer.set_frame_delay(*delay_ms as u16, 1000)?;
+            } else {
+                writer.set_frame_delay((delay_ms / 10).min(u16::MAX as u32) as u16, 100)?;
+            }
+            writer.write_image_data(pixels)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Writes lossless animated WebP files.
+///
+/// Each frame is encoded as a VP8L image and wrapped in an `ANMF` chunk that replaces the
+/// whole canvas, so frames do not depend on each other.
+pub struct WebPWriter {
+    file: File,
+    config: AnimationConfig,
+    frames: Vec<u8>,
+    frame_count: usize,
+}
+
+/// The largest canvas size and frame duration the 24-bit WebP fields can hold.
+const WEBP_MAX_24_BIT: u32 = 0x00FF_FFFF;
+
+impl WebPWriter {
+    pub fn new(path: &str, config: AnimationConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        check_canvas(&config, 1 << 14)?;
+        Ok(WebPWriter {
+            file: File::create(path)?,
+            config,
+            frames: Vec::new(),
+            frame_count: 0,
+        })
+    }
+}
+
+fn write_webp_chunk(output: &mut Vec<u8>, name: &[u8; 4], payload: &[u8]) {
+    output.extend_from_slice(name);
+    output.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    output.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        output.push(0);
+    }
+}
+
+fn push_24_bit(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&value.min(WEBP_MAX_24_BIT).to_le_bytes()[..3]);
+}
+
+impl AnimationWriter for WebPWriter {
+    fn write_frame(&mut self, frame: &AnimationFrame) -> Result<(), Box<dyn std::error::Error>> {
+        let (width, height) = (self.config.width, self.config.height);
+        let image = fit_to_canvas(&frame.image, width, height);
+        let mut still = Vec::new();
+        WebPEncoder::new(&mut still).encode(image.as_raw(), width, height, ColorType::Rgba8)?;
+
+        // A still image is the RIFF header followed by its VP8L chunk
+        let mut payload = Vec::new();
+        push_24_bit(&mut payload, 0);
+        push_24_bit(&mut payload, 0);
+        push_24_bit(&mut payload, width - 1);
+        push_24_bit(&mut payload, height - 1);
+        push_24_bit(&mut payload, frame.delay_ms);
+        // Do not blend with the previous frame and do not dispose
+        payload.push(0b10);
+        payload.extend_from_slice(&still[12..]);
+
+        write_webp_chunk(&mut self.frames, b"ANMF", &payload);
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Box<dyn std::error::Error>> {
+        if self.frame_count == 0 {
+            return Err("an animation needs at least one frame".into());
+        }
+        let mut header = Vec::new();
+        // Animation and alpha flags
+        header.extend_from_slice(&[0b0001_0010, 0, 0, 0]);
+        push_24_bit(&mut header, self.config.width - 1);
+        push_24_bit(&mut header, self.config.height - 1);
+
+        let mut animation = vec![0, 0, 0, 0];
+        animation.extend_from_slice(&(self.config.loop_count.min(u16::MAX as u32) as u16).to_le_bytes());
+
+        let mut body = b"WEBP".to_vec();
+        write_webp_chunk(&mut body, b"VP8X", &header);
+        write_webp_chunk(&mut body, b"ANIM", &animation);
+        body.extend_from_slice(&self.frames);
+
+        let mut output = BufWriter::new(&mut self.file);
+        output.write_all(b"RIFF")?;
+        output.write_all(&(body.len() as u32).to_le_bytes())?;
+        output.write_all(&body)?;
+        output.flush()?;
+        Ok(())
+    }
+}
@@ -1,6 +1,30 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::data::cdi_sector::CdiSector;
+
 const K0: [i32;4] = [0, 240, 460, 392];
 const K1: [i32;4] = [0, 0, -208, -220];
 
+/// The size of an ADPCM sound group, 18 of which fill the data of an audio sector.
+pub const SOUND_GROUP_SIZE: usize = 128;
+
+/// The previous two samples of each channel, which the prediction filters carry from one
+/// sound group to the next.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AdpcmState {
+    left: [i32; 2],
+    right: [i32; 2],
+}
+
+/// Decoded 16-bit PCM audio, with stereo samples interleaved left first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedAudio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<i16>,
+}
+
 fn limit_sample(sample: i32) -> i16 {
     if sample > i16::MAX as i32 {
         i16::MAX
@@ -11,30 +35,30 @@ fn limit_sample(sample: i32) -> i16 {
     }
 }
 
-fn decode_adpcm(su: usize, gain: i32, sd: &[&[i8]], ranges: &[u8], filters: &[u8], stereo: bool, left: &mut Vec<i16>, right: &mut Vec<i16>) -> u8 {
+/// Decodes the sound units of a sound group, appending their samples to `left` and `right`.
+///
+/// Each sample is its data scaled by `gain`, 8 for 8-bit and 12 for 4-bit data, less the range
+/// of its sound unit, plus the prediction of the unit's filter from the two previous samples of
+/// its channel. Only the lower 2 bits of a filter select the prediction. The previous samples
+/// are kept in `state`, so prediction continues across sound groups and sectors.
+#[allow(clippy::too_many_arguments)]
+fn decode_adpcm(su: usize, gain: i32, sd: &[&[i8]], ranges: &[u8], filters: &[u8], stereo: bool, state: &mut AdpcmState, left: &mut Vec<i16>, right: &mut Vec<i16>) -> u8 {
     let mut index: u8 = 0;
 
-    let mut lk0 = 0;
-    let mut rk0 = 0;
-    let mut lk1 = 0;
-    let mut rk1 = 0;
-
     for i in 0..su {
-        let cur_gain = 2u16 << (gain - ranges[i] as i32);
+        // Filters 0-3 are valid, the upper bits of the filter nibble are reserved
+        let filter = (filters[i] & 0b11) as usize;
         for ss in 0..28 {
-            if stereo && (i & 1) == 1 {
-                let sample = limit_sample((sd[i][ss] as i32 * cur_gain as i32) + ((rk0 * K0[filters[i] as usize] as i32 + rk1 * K1[filters[i] as usize] as i32) / 256));
-                rk1 = rk0;
-                rk0 = sample.into();
-                right.push(sample);
-                index += 1;
+            let (history, output) = if stereo && (i & 1) == 1 {
+                (&mut state.right, &mut *right)
             } else {
-                let sample = limit_sample((sd[i][ss] as i32 * cur_gain as i32) + ((lk0 * K0[filters[i] as usize] as i32 + lk1 * K1[filters[i] as usize] as i32) / 256));
-                lk1 = lk0;
-                lk0 = sample.into();
-                left.push(sample);
-                index += 1;
-            }
+                (&mut state.left, &mut *left)
+            };
+            let sample = limit_sample(((sd[i][ss] as i32) << gain >> ranges[i]) + ((history[0] * K0[filter] + history[1] * K1[filter]) / 256));
+            history[1] = history[0];
+            history[0] = sample.into();
+            output.push(sample);
+            index += 1;
         }
     }
 
@@ -42,7 +66,7 @@ fn decode_adpcm(su: usize, gain: i32, sd: &[&[i8]], ranges: &[u8], filters: &[u8
 }
 
 // DecodeLevelASoundGroup function takes parameters similar to the C# code
-fn decode_level_a_sound_group(stereo: bool, data: &[u8], left: &mut Vec<i16>, right: &mut Vec<i16>) -> u8 {
+fn decode_level_a_sound_group(stereo: bool, data: &[u8], state: &mut AdpcmState, left: &mut Vec<i16>, right: &mut Vec<i16>) -> u8 {
     // Initialize index to 16
     let mut index: u8 = 16;
 
@@ -72,11 +96,11 @@ fn decode_level_a_sound_group(stereo: bool, data: &[u8], left: &mut Vec<i16>, ri
         }
     }
 
-    decode_adpcm(4, 8, &sd.iter().map(|x| x.as_ref()).collect::<Vec<_>>(), &range, &filter, stereo, left, right)
+    decode_adpcm(4, 8, &sd.iter().map(|x| x.as_ref()).collect::<Vec<_>>(), &range, &filter, stereo, state, left, right)
 
 }
 
-fn decode_level_bc_sound_group(stereo: bool, data: &[u8], left: &mut Vec<i16>, right: &mut Vec<i16>) -> u8 {
+fn decode_level_bc_sound_group(stereo: bool, data: &[u8], state: &mut AdpcmState, left: &mut Vec<i16>, right: &mut Vec<i16>) -> u8 {
     let mut index: usize = 4;
     let mut range = [0u8; 8];
     let mut filter = [0u8; 8];
@@ -104,5 +128,87 @@ fn decode_level_bc_sound_group(stereo: bool, data: &[u8], left: &mut Vec<i16>, r
         }
     }
 
-    decode_adpcm(8, 12, &sd.iter().map(|x| x.as_ref()).collect::<Vec<_>>(), &range, &filter, stereo, left, right)
+    decode_adpcm(8, 12, &sd.iter().map(|x| x.as_ref()).collect::<Vec<_>>(), &range, &filter, stereo, state, left, right)
+}
+
+/// Decodes the ADPCM sound groups of audio sectors into PCM samples.
+///
+/// The sample rate, channel count and level are taken from the coding of the first sector,
+/// 8-bit sectors being level A and 4-bit sectors level B or C. The prediction filters carry
+/// on from one sector to the next, so the sectors should all belong to one channel.
+pub fn decode_audio_sectors(sectors: &[&CdiSector]) -> DecodedAudio {
+    let Some(first_sector) = sectors.first() else {
+        return DecodedAudio { sample_rate: 37800, channels: 1, samples: Vec::new() };
+    };
+    let coding_info = first_sector.coding_info();
+    let stereo = coding_info.is_stereo();
+    let is_level_a = coding_info.bits_per_sample_value() == 8;
+    let sample_rate = match coding_info.sample_rate_value() {
+        0 => 37800,
+        rate => rate,
+    };
+
+    let mut state = AdpcmState::default();
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    for sector in sectors {
+        let data = sector.get_sector_data_by_type();
+        for group in data.chunks_exact(SOUND_GROUP_SIZE) {
+            if is_level_a {
+                decode_level_a_sound_group(stereo, group, &mut state, &mut left, &mut right);
+            } else {
+                decode_level_bc_sound_group(stereo, group, &mut state, &mut left, &mut right);
+            }
+        }
+    }
+
+    let samples = if stereo {
+        left.iter().zip(&right).flat_map(|(l, r)| [*l, *r]).collect()
+    } else {
+        left
+    };
+    DecodedAudio {
+        sample_rate,
+        channels: if stereo { 2 } else { 1 },
+        samples,
+    }
+}
+
+/// Shifts audio by `offset_ms`, adding silence at the start for a positive offset and
+/// dropping samples from the start for a negative one.
+pub fn offset_audio(audio: &DecodedAudio, offset_ms: i64) -> DecodedAudio {
+    let frames = (offset_ms.unsigned_abs() * audio.sample_rate as u64 / 1000) as usize;
+    let sample_count = frames * audio.channels as usize;
+    let samples = if offset_ms >= 0 {
+        let mut samples = vec![0; sample_count];
+        samples.extend_from_slice(&audio.samples);
+        samples
+    } else {
+        audio.samples.iter().skip(sample_count).cloned().collect()
+    };
+    DecodedAudio { samples, ..audio.clone() }
+}
+
+/// Writes audio as a 16-bit PCM WAV file.
+pub fn write_wav(path: &str, audio: &DecodedAudio) -> Result<(), Box<dyn std::error::Error>> {
+    let data_size = (audio.samples.len() * 2) as u32;
+    let block_align = audio.channels * 2;
+    let mut output = BufWriter::new(File::create(path)?);
+    output.write_all(b"RIFF")?;
+    output.write_all(&(36 + data_size).to_le_bytes())?;
+    output.write_all(b"WAVEfmt ")?;
+    output.write_all(&16u32.to_le_bytes())?;
+    output.write_all(&1u16.to_le_bytes())?;
+    output.write_all(&audio.channels.to_le_bytes())?;
+    output.write_all(&audio.sample_rate.to_le_bytes())?;
+    output.write_all(&(audio.sample_rate * block_align as u32).to_le_bytes())?;
+    output.write_all(&block_align.to_le_bytes())?;
+    output.write_all(&16u16.to_le_bytes())?;
+    output.write_all(b"data")?;
+    output.write_all(&data_size.to_le_bytes())?;
+    for sample in &audio.samples {
+        output.write_all(&sample.to_le_bytes())?;
+    }
+    output.flush()?;
+    Ok(())
 }
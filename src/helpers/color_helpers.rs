@@ -54,7 +54,6 @@ pub fn read_clut_banks(data: &[u8], count: u8) -> Vec<Rgba<u8>> {
                     255,
                 ]);
                 clut_bank_colors.push(color);
-                println!("{color:?}");
             }
         }
     }
@@ -62,6 +61,88 @@ pub fn read_clut_banks(data: &[u8], count: u8) -> Vec<Rgba<u8>> {
     clut_bank_colors
 }
 
+/// A 256 entry CLUT built up from display control CLUT commands.
+///
+/// The CLUT is split into 4 banks of 64 colours. Commands are 4 byte groups, where
+/// `0xC30000??` selects bank `??` and `0x80` to `0xBF` loads the colour in the following
+/// 3 bytes into entry `command - 0x80` of the current bank. Other commands are ignored,
+/// so CLUT updates can be applied straight from a data record.
+#[derive(Clone)]
+pub struct ClutState {
+    colors: Vec<Rgba<u8>>,
+    loaded: Vec<bool>,
+    bank: u8,
+}
+
+impl Default for ClutState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClutState {
+    pub const CLUT_SIZE: usize = 256;
+    pub const BANK_SIZE: usize = 64;
+
+    pub fn new() -> Self {
+        ClutState {
+            colors: vec![Rgba([0, 0, 0, 255]); Self::CLUT_SIZE],
+            loaded: vec![false; Self::CLUT_SIZE],
+            bank: 0,
+        }
+    }
+
+    /// Applies a sequence of CLUT commands and returns the number of colours loaded.
+    ///
+    /// Entries that are not loaded by the commands keep their previous colour.
+    pub fn apply_commands(&mut self, data: &[u8]) -> usize {
+        let mut loaded_count = 0;
+        for command in data.chunks_exact(4) {
+            match command[0] {
+                0xC3 => self.bank = command[3] & 0b11,
+                0x80..=0xBF => {
+                    let index = self.bank as usize * Self::BANK_SIZE + (command[0] - 0x80) as usize;
+                    self.colors[index] = Rgba([command[1], command[2], command[3], 255]);
+                    self.loaded[index] = true;
+                    loaded_count += 1;
+                }
+                _ => {}
+            }
+        }
+        loaded_count
+    }
+
+    /// Loads colours directly from `start_index` onwards, as with a palette read from a file.
+    ///
+    /// Colours past the end of the CLUT are ignored.
+    pub fn load_colors(&mut self, start_index: usize, colors: &[Rgba<u8>]) {
+        for (index, color) in (start_index..Self::CLUT_SIZE).zip(colors) {
+            self.colors[index] = *color;
+            self.loaded[index] = true;
+        }
+    }
+
+    pub fn colors(&self) -> &[Rgba<u8>] {
+        &self.colors
+    }
+
+    pub fn color(&self, index: u8) -> Rgba<u8> {
+        self.colors[index as usize]
+    }
+
+    pub fn bank(&self) -> u8 {
+        self.bank
+    }
+
+    pub fn is_loaded(&self, index: u8) -> bool {
+        self.loaded[index as usize]
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        self.loaded.iter().filter(|l| **l).count()
+    }
+}
+
 /// Writes a `Vec<Rgba<u8>>` palette to a png file.
 ///
 /// Writes a palette represented by a vector of `Rgba<u8>` colours,
@@ -96,3 +177,144 @@ pub fn write_palette(path: &str, colors: &[Rgba<u8>]) -> Result<(), Box<dyn std:
 
     Ok(())
 }
+
+/// Writes a palette to an Adobe Color Table (.act) file.
+///
+/// Writes 256 RGB entries, padding short palettes with black, followed by the
+/// colour count and the index of the first fully transparent colour, or `0xFFFF` if none.
+/// The format only holds one transparent index, so any later transparent colours are written
+/// as opaque and read back that way.
+pub fn write_act_palette(path: &str, colors: &[Rgba<u8>]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut data = vec![0u8; 768];
+    for (index, color) in colors.iter().take(256).enumerate() {
+        data[index * 3..index * 3 + 3].copy_from_slice(&color.0[..3]);
+    }
+    let count = colors.len().min(256) as u16;
+    let transparent_index = colors
+        .iter()
+        .take(256)
+        .position(|c| c[3] == 0)
+        .map_or(0xFFFF, |i| i as u16);
+    data.extend(count.to_be_bytes());
+    data.extend(transparent_index.to_be_bytes());
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+/// Reads a palette from an Adobe Color Table (.act) file.
+///
+/// Honours the optional colour count and transparent index that follow the 256 entries.
+pub fn read_act_palette(path: &str) -> Result<Vec<Rgba<u8>>, Box<dyn std::error::Error>> {
+    let data = std::fs::read(path)?;
+    if data.len() < 768 {
+        return Err(format!("ACT palette is {} bytes, expected at least 768", data.len()).into());
+    }
+    let mut colors = read_unindexed_palette(&data[..768]);
+    if data.len() >= 772 {
+        let count = u16::from_be_bytes([data[768], data[769]]) as usize;
+        let transparent_index = u16::from_be_bytes([data[770], data[771]]) as usize;
+        if count > 0 && count <= 256 {
+            colors.truncate(count);
+        }
+        if let Some(color) = colors.get_mut(transparent_index) {
+            color[3] = 0;
+        }
+    }
+    Ok(colors)
+}
+
+/// Writes a palette to a GIMP palette (.gpl) file, named after the file.
+pub fn write_gpl_palette(path: &str, colors: &[Rgba<u8>]) -> Result<(), Box<dyn std::error::Error>> {
+    let name = std::path::Path::new(path)
+        .file_stem()
+        .map_or("Palette".to_string(), |s| s.to_string_lossy().to_string());
+    let mut text = format!("GIMP Palette\nName: {name}\nColumns: 16\n#\n");
+    for (index, color) in colors.iter().enumerate() {
+        text.push_str(&format!("{:3} {:3} {:3}\tIndex {index}\n", color[0], color[1], color[2]));
+    }
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// Reads a palette from a GIMP palette (.gpl) file.
+pub fn read_gpl_palette(path: &str) -> Result<Vec<Rgba<u8>>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some("GIMP Palette") {
+        return Err("missing GIMP Palette header".into());
+    }
+
+    let mut colors = Vec::new();
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        let values: Vec<&str> = line.split_whitespace().take(3).collect();
+        colors.push(parse_rgb_values(&values)?);
+    }
+    Ok(colors)
+}
+
+/// Writes a palette to a JASC (Paint Shop Pro) palette (.pal) file.
+pub fn write_jasc_palette(path: &str, colors: &[Rgba<u8>]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut text = format!("JASC-PAL\r\n0100\r\n{}\r\n", colors.len());
+    for color in colors {
+        text.push_str(&format!("{} {} {}\r\n", color[0], color[1], color[2]));
+    }
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// Reads a palette from a JASC (Paint Shop Pro) palette (.pal) file.
+pub fn read_jasc_palette(path: &str) -> Result<Vec<Rgba<u8>>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut lines = text.lines().map(str::trim);
+    if lines.next() != Some("JASC-PAL") {
+        return Err("missing JASC-PAL header".into());
+    }
+    lines.next();
+    let count: usize = lines.next().ok_or("missing JASC-PAL colour count")?.parse()?;
+
+    let color_lines: Vec<&str> = lines.filter(|l| !l.is_empty()).collect();
+    if color_lines.len() != count {
+        return Err(format!("JASC-PAL palette declares {} colours but holds {}", count, color_lines.len()).into());
+    }
+    let mut colors = Vec::with_capacity(count);
+    for line in color_lines {
+        let values: Vec<&str> = line.split_whitespace().take(3).collect();
+        colors.push(parse_rgb_values(&values)?);
+    }
+    Ok(colors)
+}
+
+/// Writes a palette as a plain list of `rrggbb` hex values, one per line, as used by Aseprite and Lospec.
+pub fn write_hex_palette(path: &str, colors: &[Rgba<u8>]) -> Result<(), Box<dyn std::error::Error>> {
+    let text: String = colors
+        .iter()
+        .map(|c| format!("{:02x}{:02x}{:02x}\n", c[0], c[1], c[2]))
+        .collect();
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// Reads a palette from a plain list of hex values, with or without a leading `#`.
+pub fn read_hex_palette(path: &str) -> Result<Vec<Rgba<u8>>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut colors = Vec::new();
+    for line in text.lines().map(|l| l.trim().trim_start_matches('#')).filter(|l| !l.is_empty()) {
+        if line.len() != 6 {
+            return Err(format!("invalid hex colour `{line}`").into());
+        }
+        let value = u32::from_str_radix(line, 16)?;
+        colors.push(Rgba([(value >> 16) as u8, (value >> 8) as u8, value as u8, 255]));
+    }
+    Ok(colors)
+}
+
+fn parse_rgb_values(values: &[&str]) -> Result<Rgba<u8>, Box<dyn std::error::Error>> {
+    if values.len() != 3 {
+        return Err(format!("expected 3 colour values, found {}", values.len()).into());
+    }
+    Ok(Rgba([values[0].parse()?, values[1].parse()?, values[2].parse()?, 255]))
+}
@@ -0,0 +1,152 @@
+use image::{ImageBuffer, Rgba};
+
+/// Decides which pixels of a plane are transparent, following the MCD212 transparency control.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransparencyMode {
+    /// The whole plane is transparent.
+    Always,
+    /// The whole plane is opaque.
+    Never,
+    /// Pixels whose colour matches the key are transparent.
+    ColorKey(Rgba<u8>),
+    /// Pixels whose colour does not match the key are transparent.
+    ColorKeyInverted(Rgba<u8>),
+    /// Pixels decoded as transparent, such as the RGB555 transparency bit or CLUT transparency, are transparent.
+    TransparentBit,
+    /// Pixels decoded as opaque are transparent.
+    TransparentBitInverted,
+    /// Pixels outside every matte are transparent.
+    MatteFlag0,
+    /// Pixels inside a matte are transparent.
+    MatteFlag1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaneOrder {
+    AInFront,
+    BInFront,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlaneSettings {
+    pub transparency: TransparencyMode,
+    /// The image contribution factor, from 0 (black) to 63 (full intensity).
+    pub image_contribution: u8,
+}
+
+impl Default for PlaneSettings {
+    fn default() -> Self {
+        PlaneSettings {
+            transparency: TransparencyMode::TransparentBit,
+            image_contribution: 63,
+        }
+    }
+}
+
+/// A rectangle in display coordinates where the matte flag is set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Matte {
+    pub x_start: u32,
+    pub x_end: u32,
+    pub y_start: u32,
+    pub y_end: u32,
+}
+
+impl Matte {
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x_start && x < self.x_end && y >= self.y_start && y < self.y_end
+    }
+}
+
+pub struct CompositeConfig {
+    pub plane_a: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    pub plane_b: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    pub plane_a_settings: PlaneSettings,
+    pub plane_b_settings: PlaneSettings,
+    pub plane_order: PlaneOrder,
+    pub use_mixing: bool,
+    pub backdrop: Rgba<u8>,
+    pub mattes: Vec<Matte>,
+}
+
+/// Returns the colour of a 4-bit `IRGB` backdrop value.
+///
+/// Each colour bit selects full intensity, halved when the intensity bit is clear.
+pub fn backdrop_color(irgb: u8) -> Rgba<u8> {
+    let level = if irgb & 0b1000 != 0 { 255 } else { 128 };
+    let channel = |bit: u8| if irgb & bit != 0 { level } else { 0 };
+    Rgba([channel(0b0100), channel(0b0010), channel(0b0001), 255])
+}
+
+/// Combines plane A and plane B into the frame the MCD212 would display.
+///
+/// The output is as large as the larger plane, with a smaller plane scaled by pixel
+/// repetition, so a normal resolution plane B lines up with a double resolution plane A.
+/// Each plane pixel is first tested for transparency and scaled by its image contribution
+/// factor. Without mixing, the front plane is shown where it is opaque, then the back plane,
+/// then the backdrop. With mixing, the opaque pixels of both planes are added together and
+/// the backdrop only shows where both planes are transparent.
+pub fn composite_planes(config: CompositeConfig) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let width = config.plane_a.width().max(config.plane_b.width());
+    let height = config.plane_a.height().max(config.plane_b.height());
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let is_matte = config.mattes.iter().any(|m| m.contains(x, y));
+        let a = plane_pixel(&config.plane_a, &config.plane_a_settings, x, y, width, height, is_matte);
+        let b = plane_pixel(&config.plane_b, &config.plane_b_settings, x, y, width, height, is_matte);
+        let (front, back) = match config.plane_order {
+            PlaneOrder::AInFront => (a, b),
+            PlaneOrder::BInFront => (b, a),
+        };
+
+        if config.use_mixing {
+            match (front, back) {
+                (None, None) => config.backdrop,
+                (Some(color), None) | (None, Some(color)) => color,
+                (Some(front), Some(back)) => Rgba([
+                    front[0].saturating_add(back[0]),
+                    front[1].saturating_add(back[1]),
+                    front[2].saturating_add(back[2]),
+                    255,
+                ]),
+            }
+        } else {
+            front.or(back).unwrap_or(config.backdrop)
+        }
+    })
+}
+
+/// Returns the contribution of a plane at a display position, or `None` when it is transparent.
+fn plane_pixel(
+    plane: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    settings: &PlaneSettings,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    is_matte: bool,
+) -> Option<Rgba<u8>> {
+    if plane.width() == 0 || plane.height() == 0 {
+        return None;
+    }
+    let pixel = *plane.get_pixel(x * plane.width() / width, y * plane.height() / height);
+    let matches_key = |key: &Rgba<u8>| pixel.0[..3] == key.0[..3];
+
+    let is_transparent = match settings.transparency {
+        TransparencyMode::Always => true,
+        TransparencyMode::Never => false,
+        TransparencyMode::ColorKey(key) => matches_key(&key),
+        TransparencyMode::ColorKeyInverted(key) => !matches_key(&key),
+        TransparencyMode::TransparentBit => pixel[3] == 0,
+        TransparencyMode::TransparentBitInverted => pixel[3] != 0,
+        TransparencyMode::MatteFlag0 => !is_matte,
+        TransparencyMode::MatteFlag1 => is_matte,
+    };
+    if is_transparent {
+        return None;
+    }
+
+    let contribution = settings.image_contribution.min(63) as u32;
+    let scale = |channel: u8| (channel as u32 * contribution / 63) as u8;
+    Some(Rgba([scale(pixel[0]), scale(pixel[1]), scale(pixel[2]), 255]))
+}
@@ -0,0 +1,239 @@
+use image::{imageops::FilterType, ImageBuffer, Rgba};
+
+use crate::data::cdi_sector::CdiSector;
+use crate::data::{CdiResolution, CdiVideoType};
+use crate::helpers::image_analysis_helpers::guess_image_dimensions;
+use crate::helpers::image_format_helpers::{
+    decode_clut4_image, decode_clut7_image, decode_clut8_image, decode_dyuv_image, decode_qhy_image,
+    decode_rgb555_image, decode_rl3_image, decode_rl7_lines, decode_rle_image, Clut4Config, Clut7Config, DecodeError,
+    DyuvImageConfig, DyuvStartValues, QhyImageConfig, Rgb555Config, RleImageConfig,
+};
+use crate::helpers::resolution_helpers::{correct_aspect_ratio, scale_to_resolution, VideoStandard};
+
+/// The display state used to decode video records.
+///
+/// A `width` or `height` of 0 is guessed from the record with `guess_image_dimensions`,
+/// except for run-length records of a known width, whose height is the number of lines
+/// they hold.
+/// When `display_resolution` is set, images are scaled by pixel repetition from the resolution
+/// of their record to it, and when `aspect_correction` is set, they are then rescaled to the
+/// proportions of that display standard.
+#[derive(Clone)]
+pub struct DecodeContext {
+    pub clut: Vec<Rgba<u8>>,
+    pub dyuv_start_values: DyuvStartValues,
+    pub width: u32,
+    pub height: u32,
+    pub use_transparency: bool,
+    pub transparency_index: u8,
+    pub use_lower_indexes: bool,
+    pub high_nibble_first: bool,
+    pub display_resolution: Option<CdiResolution>,
+    pub aspect_correction: Option<VideoStandard>,
+}
+
+impl Default for DecodeContext {
+    fn default() -> Self {
+        DecodeContext {
+            clut: Vec::new(),
+            dyuv_start_values: DyuvStartValues { y: 16, u: 128, v: 128 },
+            width: 0,
+            height: 0,
+            use_transparency: false,
+            transparency_index: 0,
+            use_lower_indexes: false,
+            high_nibble_first: true,
+            display_resolution: None,
+            aspect_correction: None,
+        }
+    }
+}
+
+impl DecodeContext {
+    pub fn new(clut: Vec<Rgba<u8>>) -> Self {
+        DecodeContext {
+            clut,
+            ..Default::default()
+        }
+    }
+}
+
+/// Drops the zero bytes that pad run-length data to the end of its last sector, keeping
+/// the zero length byte of a final end of line run.
+fn trim_rle_padding(data: &[u8]) -> &[u8] {
+    let mut end = 0;
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] & 0x80 == 0 {
+            if data[i] != 0 {
+                end = i + 1;
+            }
+            i += 1;
+        } else {
+            i += 2;
+            end = i.min(data.len());
+        }
+    }
+    &data[..end]
+}
+
+/// Decodes the sectors of a video record into an RGBA image.
+///
+/// The decoder is chosen from the coding of the first video sector, or QHY when the record
+/// holds any QHY sectors. RGB555 records must hold both their RGB555L and RGB555H sectors,
+/// and QHY records both their DYUV base and QHY detail sectors, which are separated by coding.
+/// Audio and data sectors in the record are ignored, as their coding bytes mean something else.
+pub fn decode_video_record(record: &[&CdiSector], context: &DecodeContext) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    let qhy_sector = record
+        .iter()
+        .find(|s| s.submode().is_video() && s.coding_info().coding() == CdiVideoType::QHY as u8);
+    let first_sector = qhy_sector
+        .or_else(|| record.iter().find(|s| s.submode().is_video()))
+        .ok_or(DecodeError::EmptyRecord)?;
+    let coding_info = first_sector.coding_info();
+    let coding = coding_info.coding();
+    if coding > CdiVideoType::QHY as u8 {
+        return Err(DecodeError::UnsupportedCoding(coding));
+    }
+
+    let data_by_coding = |codings: &[u8]| -> Vec<u8> {
+        record
+            .iter()
+            .filter(|s| s.submode().is_video() && codings.contains(&s.coding_info().coding()))
+            .flat_map(|s| s.get_sector_data_by_type())
+            .collect()
+    };
+    let data = data_by_coding(&[coding]);
+    if data.is_empty() {
+        return Err(DecodeError::EmptyRecord);
+    }
+    let dyuv_base_data = if coding == CdiVideoType::QHY as u8 {
+        data_by_coding(&[CdiVideoType::DYUV as u8])
+    } else {
+        Vec::new()
+    };
+    let dimension_data = if dyuv_base_data.is_empty() { &data } else { &dyuv_base_data };
+
+    let is_rle = coding == CdiVideoType::RL3 as u8 || coding == CdiVideoType::RL7 as u8;
+    let (width, height) = if context.width != 0 && context.height != 0 {
+        (context.width, context.height)
+    } else if context.width != 0 && is_rle {
+        // Pixel counts of RL3 runs and single bytes are pairs, so lines are counted in pairs
+        let line_width = if coding == CdiVideoType::RL3 as u8 { context.width.div_ceil(2) } else { context.width };
+        let line_count = decode_rl7_lines(trim_rle_padding(&data), line_width as usize, 0).line_count();
+        if line_count == 0 {
+            return Err(DecodeError::EmptyRecord);
+        }
+        (context.width, line_count as u32)
+    } else {
+        let candidate = guess_image_dimensions(dimension_data, coding_info)
+            .into_iter()
+            .next()
+            .ok_or(DecodeError::UnknownDimensions)?;
+        (
+            if context.width != 0 { context.width } else { candidate.width },
+            if context.height != 0 { context.height } else { candidate.height },
+        )
+    };
+
+    let clut_config = |encoded_data: Vec<u8>| Clut7Config {
+        width,
+        height,
+        encoded_data,
+        clut_data: context.clut.clone(),
+        use_transparency: context.use_transparency,
+        transparency_index: context.transparency_index,
+        use_lower_indexes: context.use_lower_indexes,
+    };
+    let rle_config = |encoded_data: Vec<u8>| -> Result<RleImageConfig, DecodeError> {
+        if context.clut.is_empty() {
+            return Err(DecodeError::MissingClut);
+        }
+        Ok(RleImageConfig {
+            encoded_data,
+            line_width: width as usize,
+            height: height as usize,
+            clut_data: context.clut.clone(),
+            use_transparency: context.use_transparency,
+        })
+    };
+    let start = context.dyuv_start_values;
+
+    let image = match coding {
+        c if c == CdiVideoType::CLUT4 as u8 => {
+            let config = clut_config(data);
+            decode_clut4_image(Clut4Config {
+                width: config.width,
+                height: config.height,
+                encoded_data: config.encoded_data,
+                clut_data: config.clut_data,
+                use_transparency: config.use_transparency,
+                transparency_index: config.transparency_index,
+                use_lower_indexes: config.use_lower_indexes,
+                high_nibble_first: context.high_nibble_first,
+            })?
+        }
+        c if c == CdiVideoType::CLUT7 as u8 => decode_clut7_image(clut_config(data))?,
+        c if c == CdiVideoType::CLUT8 as u8 => decode_clut8_image(clut_config(data))?,
+        c if c == CdiVideoType::RL3 as u8 => decode_rl3_image(rle_config(data)?)?,
+        c if c == CdiVideoType::RL7 as u8 => decode_rle_image(rle_config(data)?)?,
+        c if c == CdiVideoType::DYUV as u8 => decode_dyuv_image(DyuvImageConfig {
+            width,
+            height,
+            encoded_data: data,
+            initial_y: start.y as u32,
+            initial_u: start.u as u32,
+            initial_v: start.v as u32,
+        })?,
+        c if c == CdiVideoType::RGB555L as u8 || c == CdiVideoType::RGB555H as u8 => {
+            let lower_data = data_by_coding(&[CdiVideoType::RGB555L as u8]);
+            let upper_data = data_by_coding(&[CdiVideoType::RGB555H as u8]);
+            if lower_data.is_empty() {
+                return Err(DecodeError::MissingPlane("RGB555L".to_string()));
+            }
+            if upper_data.is_empty() {
+                return Err(DecodeError::MissingPlane("RGB555H".to_string()));
+            }
+            decode_rgb555_image(Rgb555Config {
+                width,
+                height,
+                lower_data,
+                upper_data,
+                use_transparency: context.use_transparency,
+                transparent_bit_set: false,
+            })?
+        }
+        c if c == CdiVideoType::QHY as u8 => {
+            if dyuv_base_data.is_empty() {
+                return Err(DecodeError::MissingPlane("DYUV".to_string()));
+            }
+            decode_qhy_image(QhyImageConfig {
+                width,
+                height,
+                dyuv_data: dyuv_base_data,
+                qhy_data: data,
+                initial_y: start.y as u32,
+                initial_u: start.u as u32,
+                initial_v: start.v as u32,
+            })?
+        }
+        _ => return Err(DecodeError::UnsupportedCoding(coding)),
+    };
+
+    // QHY doubles the horizontal resolution of its DYUV base plane
+    let resolution = if coding == CdiVideoType::QHY as u8 {
+        CdiResolution::Double
+    } else {
+        coding_info.resolution_type()
+    };
+    let (image, resolution) = match context.display_resolution {
+        Some(display_resolution) => (scale_to_resolution(&image, resolution, display_resolution), display_resolution),
+        None => (image, resolution),
+    };
+    let image = match context.aspect_correction {
+        Some(standard) => correct_aspect_ratio(&image, resolution, standard, FilterType::Triangle),
+        None => image,
+    };
+
+    Ok(image)
+}
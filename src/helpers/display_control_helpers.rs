@@ -0,0 +1,275 @@
+use image::{ImageBuffer, Rgba};
+
+use crate::helpers::color_helpers::ClutState;
+use crate::helpers::compositing_helpers::{PlaneOrder, TransparencyMode};
+use crate::helpers::image_format_helpers::{
+    decode_dyuv_image_with_line_starts, decode_rl7_lines, DecodeError, DyuvImageConfig, DyuvStartValues,
+};
+
+/// The number of instructions the MCD212 reads for each line of a display control program.
+pub const INSTRUCTIONS_PER_LINE: usize = 16;
+
+/// A single MCD212 display control instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayInstruction {
+    Stop,
+    Nop,
+    ReloadDcp(u32),
+    ReloadDcpAndStop(u32),
+    ReloadVsr(u32),
+    ReloadVsrAndStop(u32),
+    Interrupt,
+    ReloadDisplayParameters(u8),
+    /// Loads a 24-bit value into a register, `0x80`-`0xBF` being CLUT colours.
+    LoadRegister { register: u8, value: u32 },
+}
+
+impl DisplayInstruction {
+    pub fn from_u32(instruction: u32) -> Self {
+        let opcode = (instruction >> 24) as u8;
+        let operand = instruction & 0x00FF_FFFF;
+        if opcode & 0x80 != 0 {
+            return DisplayInstruction::LoadRegister { register: opcode, value: operand };
+        }
+        match opcode >> 4 {
+            0 => DisplayInstruction::Stop,
+            2 => DisplayInstruction::ReloadDcp(operand),
+            3 => DisplayInstruction::ReloadDcpAndStop(operand),
+            4 => DisplayInstruction::ReloadVsr(operand),
+            5 => DisplayInstruction::ReloadVsrAndStop(operand),
+            6 => DisplayInstruction::Interrupt,
+            7 => DisplayInstruction::ReloadDisplayParameters(opcode & 0x0F),
+            _ => DisplayInstruction::Nop,
+        }
+    }
+
+    fn stops(&self) -> bool {
+        matches!(
+            self,
+            DisplayInstruction::Stop | DisplayInstruction::ReloadDcpAndStop(_) | DisplayInstruction::ReloadVsrAndStop(_)
+        )
+    }
+}
+
+/// Reads big endian display control instructions, ignoring any trailing partial instruction.
+pub fn parse_display_instructions(data: &[u8]) -> Vec<DisplayInstruction> {
+    data.chunks_exact(4)
+        .map(|i| DisplayInstruction::from_u32(u32::from_be_bytes([i[0], i[1], i[2], i[3]])))
+        .collect()
+}
+
+/// A display control program, made of the instructions run once before the first line
+/// and the line control table run at the start of each line.
+pub struct DisplayControlProgram {
+    pub frame_instructions: Vec<DisplayInstruction>,
+    pub line_instructions: Vec<Vec<DisplayInstruction>>,
+}
+
+impl DisplayControlProgram {
+    /// Splits a line control table into blocks of `instructions_per_line` instructions.
+    pub fn from_line_control_table(frame_data: &[u8], table_data: &[u8], instructions_per_line: usize) -> Self {
+        DisplayControlProgram {
+            frame_instructions: parse_display_instructions(frame_data),
+            line_instructions: table_data
+                .chunks(instructions_per_line.max(1) * 4)
+                .map(parse_display_instructions)
+                .collect(),
+        }
+    }
+}
+
+/// The display state of one channel of the MCD212 for a line.
+#[derive(Clone)]
+pub struct DisplayParameters {
+    pub clut: ClutState,
+    pub image_coding: u32,
+    pub transparency_control: u32,
+    pub plane_order: u32,
+    pub transparent_color_a: Rgba<u8>,
+    pub transparent_color_b: Rgba<u8>,
+    pub dyuv_start_a: DyuvStartValues,
+    pub dyuv_start_b: DyuvStartValues,
+    pub backdrop: u8,
+    pub image_contribution_a: u8,
+    pub image_contribution_b: u8,
+    pub display_parameters: u8,
+    /// The address loaded by the last video start register reload on this line.
+    pub image_start: Option<u32>,
+}
+
+impl Default for DisplayParameters {
+    fn default() -> Self {
+        let dyuv_start = DyuvStartValues { y: 16, u: 128, v: 128 };
+        DisplayParameters {
+            clut: ClutState::new(),
+            image_coding: 0,
+            transparency_control: 0,
+            plane_order: 0,
+            transparent_color_a: Rgba([0, 0, 0, 255]),
+            transparent_color_b: Rgba([0, 0, 0, 255]),
+            dyuv_start_a: dyuv_start,
+            dyuv_start_b: dyuv_start,
+            backdrop: 0,
+            image_contribution_a: 63,
+            image_contribution_b: 63,
+            display_parameters: 0,
+            image_start: None,
+        }
+    }
+}
+
+impl DisplayParameters {
+    /// Applies instructions until the first stop instruction.
+    pub fn apply(&mut self, instructions: &[DisplayInstruction]) {
+        for instruction in instructions {
+            match instruction {
+                DisplayInstruction::ReloadVsr(address) | DisplayInstruction::ReloadVsrAndStop(address) => {
+                    self.image_start = Some(*address);
+                }
+                DisplayInstruction::ReloadDisplayParameters(parameters) => self.display_parameters = *parameters,
+                DisplayInstruction::LoadRegister { register, value } => self.load_register(*register, *value),
+                _ => {}
+            }
+            if instruction.stops() {
+                break;
+            }
+        }
+    }
+
+    fn load_register(&mut self, register: u8, value: u32) {
+        let color = Rgba([(value >> 16) as u8, (value >> 8) as u8, value as u8, 255]);
+        let dyuv_start = DyuvStartValues { y: color[0], u: color[1], v: color[2] };
+        match register {
+            0x80..=0xBF | 0xC3 => {
+                self.clut.apply_commands(&((register as u32) << 24 | value).to_be_bytes());
+            }
+            0xC0 => self.image_coding = value,
+            0xC1 => self.transparency_control = value,
+            0xC2 => self.plane_order = value,
+            0xC4 => self.transparent_color_a = color,
+            0xC6 => self.transparent_color_b = color,
+            0xCA => self.dyuv_start_a = dyuv_start,
+            0xCB => self.dyuv_start_b = dyuv_start,
+            0xD8 => self.backdrop = (value & 0x0F) as u8,
+            0xDB => self.image_contribution_a = (value & 0x3F) as u8,
+            0xDC => self.image_contribution_b = (value & 0x3F) as u8,
+            _ => {}
+        }
+    }
+
+    pub fn plane_order(&self) -> PlaneOrder {
+        if self.plane_order & 1 == 0 {
+            PlaneOrder::AInFront
+        } else {
+            PlaneOrder::BInFront
+        }
+    }
+
+    pub fn transparency_mode_a(&self) -> TransparencyMode {
+        transparency_mode((self.transparency_control & 0x0F) as u8, self.transparent_color_a)
+    }
+
+    pub fn transparency_mode_b(&self) -> TransparencyMode {
+        transparency_mode(((self.transparency_control >> 8) & 0x0F) as u8, self.transparent_color_b)
+    }
+}
+
+fn transparency_mode(control: u8, key: Rgba<u8>) -> TransparencyMode {
+    match control {
+        0 => TransparencyMode::Always,
+        1 => TransparencyMode::ColorKey(key),
+        2 => TransparencyMode::TransparentBit,
+        3 | 12 => TransparencyMode::MatteFlag0,
+        4 | 11 => TransparencyMode::MatteFlag1,
+        9 => TransparencyMode::ColorKeyInverted(key),
+        10 => TransparencyMode::TransparentBitInverted,
+        _ => TransparencyMode::Never,
+    }
+}
+
+/// Runs a display control program and returns the display state of each line.
+///
+/// The frame instructions are applied once, then the line control table entry of each
+/// line, so CLUT and register changes carry over to the following lines. Video start
+/// reloads only apply to the line that loads them. Jumps to other display control
+/// programs are not followed.
+pub fn simulate_display_program(program: &DisplayControlProgram, height: u32, initial: DisplayParameters) -> Vec<DisplayParameters> {
+    let mut state = initial;
+    state.apply(&program.frame_instructions);
+
+    let mut lines = Vec::with_capacity(height as usize);
+    for y in 0..height as usize {
+        state.image_start = None;
+        if let Some(instructions) = program.line_instructions.get(y) {
+            state.apply(instructions);
+        }
+        lines.push(state.clone());
+    }
+    lines
+}
+
+/// Returns the offset of each line within plane memory.
+///
+/// Lines with a video start reload begin at that address relative to `base_address`,
+/// other lines continue where the previous line ended.
+fn line_offset(line: &DisplayParameters, base_address: u32, next_offset: usize) -> usize {
+    match line.image_start {
+        Some(address) => address.saturating_sub(base_address) as usize,
+        None => next_offset,
+    }
+}
+
+/// Renders CLUT7 or CLUT8 plane memory using the CLUT and video start of each line.
+pub fn render_clut_lines(memory: &[u8], base_address: u32, width: u32, lines: &[DisplayParameters]) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    let mut image = ImageBuffer::new(width, lines.len() as u32);
+    let mut next_offset = 0;
+    for (y, line) in lines.iter().enumerate() {
+        let offset = line_offset(line, base_address, next_offset);
+        let end = offset + width as usize;
+        if end > memory.len() {
+            return Err(DecodeError::InsufficientData { expected: end, actual: memory.len() });
+        }
+        for (x, index) in memory[offset..end].iter().enumerate() {
+            image.put_pixel(x as u32, y as u32, line.clut.color(*index));
+        }
+        next_offset = end;
+    }
+    Ok(image)
+}
+
+/// Renders RL7 plane memory using the CLUT and video start of each line.
+///
+/// Lines without a video start reload continue where the previous line's runs ended.
+pub fn render_rl7_lines(memory: &[u8], base_address: u32, width: u32, lines: &[DisplayParameters]) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    if width == 0 {
+        return Err(DecodeError::InvalidDimensions { width, height: lines.len() as u32 });
+    }
+    let mut image = ImageBuffer::new(width, lines.len() as u32);
+    let mut next_offset = 0;
+    for (y, line) in lines.iter().enumerate() {
+        let offset = line_offset(line, base_address, next_offset).min(memory.len());
+        let decoded = decode_rl7_lines(&memory[offset..], width as usize, 2);
+        for (x, index) in decoded.line(0).iter().enumerate() {
+            image.put_pixel(x as u32, y as u32, line.clut.color(*index));
+        }
+        next_offset = offset + decoded.line_offsets[1];
+    }
+    Ok(image)
+}
+
+/// Renders a DYUV plane using the plane A start values of each line.
+pub fn render_dyuv_lines(encoded_data: Vec<u8>, width: u32, lines: &[DisplayParameters]) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    let line_starts: Vec<DyuvStartValues> = lines.iter().map(|l| l.dyuv_start_a).collect();
+    let first = line_starts.first().copied().unwrap_or(DyuvStartValues { y: 16, u: 128, v: 128 });
+    decode_dyuv_image_with_line_starts(
+        DyuvImageConfig {
+            width,
+            height: lines.len() as u32,
+            encoded_data,
+            initial_y: first.y as u32,
+            initial_u: first.u as u32,
+            initial_v: first.v as u32,
+        },
+        &line_starts,
+    )
+}
@@ -0,0 +1,170 @@
+use std::fs;
+use std::path::Path;
+
+use crate::data::cdi_file::CdiFile;
+use crate::data::cdi_sector::CdiSector;
+use crate::data::{CdiPaletteType, CdiVideoType};
+use crate::helpers::color_helpers::ClutState;
+use crate::helpers::decode_helpers::{decode_video_record, DecodeContext};
+use crate::helpers::palette_discovery_helpers::{find_palette_candidates, PaletteCandidate};
+
+pub struct ExtractOptions {
+    pub output_dir: String,
+    /// Image files are named `{file_prefix}_{number}.png`.
+    pub file_prefix: String,
+    /// Only records of this channel are extracted when set.
+    pub channel: Option<u8>,
+    /// The decode settings, whose CLUT is the starting point that palettes found are loaded onto.
+    pub context: DecodeContext,
+    pub min_palette_confidence: f32,
+}
+
+impl ExtractOptions {
+    pub fn new(output_dir: &str) -> Self {
+        ExtractOptions {
+            output_dir: output_dir.to_string(),
+            file_prefix: "image".to_string(),
+            channel: None,
+            context: DecodeContext::default(),
+            min_palette_confidence: 0.5,
+        }
+    }
+}
+
+/// The outcome of decoding one video record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtractedRecord {
+    pub first_sector: u32,
+    pub last_sector: u32,
+    pub channel: u8,
+    pub coding: String,
+    /// The last data sector to have changed the CLUT before the record, if any.
+    pub palette_sector: Option<u32>,
+    /// The file name and dimensions of the saved image, or the reason it was not saved.
+    pub result: Result<(String, u32, u32), String>,
+}
+
+/// Decodes every video record of a file and saves the images as numbered PNGs.
+///
+/// Records are visited in the order of their first sector. Only video records are decoded, as
+/// data sectors carry no coding; they are only searched for palettes with
+/// `find_palette_candidates`. Palettes are loaded onto one running CLUT in the order they
+/// appear, so partial updates such as separate bank 0 and bank 1 loads add up. Every CLUT
+/// command palette of a sector is replayed, while sectors without one load only their most
+/// confident palette. Each record is decoded with the full CLUT as it stood at its first
+/// sector. MPEG records are skipped.
+/// A `manifest.json` describing every record, saved or not, is written next to the images.
+pub fn extract_all_images(file: &CdiFile, options: &ExtractOptions) -> Result<Vec<ExtractedRecord>, Box<dyn std::error::Error>> {
+    fs::create_dir_all(&options.output_dir)?;
+
+    let palettes = palette_updates(find_palette_candidates(file, options.min_palette_confidence));
+    let mut clut = ClutState::new();
+    clut.load_colors(0, &options.context.clut);
+    let mut palette_sector = None;
+    let mut next_palette = 0;
+
+    let mut records: Vec<Vec<&CdiSector>> = file
+        .get_video_records()
+        .into_iter()
+        .filter(|r| options.channel.is_none_or(|c| r[0].channel_number() == c))
+        .filter(|r| r.iter().any(|s| s.coding_info().coding() != CdiVideoType::MPEG as u8))
+        .collect();
+    records.sort_by_key(|r| r[0].sector_index());
+
+    let mut extracted = Vec::new();
+    let mut image_number = 0;
+    for record in &records {
+        let first_sector = record[0].sector_index();
+        while let Some(palette) = palettes.get(next_palette).filter(|p| p.sector_index < first_sector) {
+            if palette.palette_type == CdiPaletteType::Clut {
+                clut.apply_commands(&palette.commands);
+            } else {
+                clut.load_colors(palette.start_index, &palette.colors);
+            }
+            palette_sector = Some(palette.sector_index);
+            next_palette += 1;
+        }
+        let mut context = options.context.clone();
+        if palette_sector.is_some() {
+            context.clut = clut.colors().to_vec();
+        }
+
+        let result = match decode_video_record(record, &context) {
+            Ok(image) => {
+                let file_name = format!("{}_{:04}.png", options.file_prefix, image_number);
+                image.save(Path::new(&options.output_dir).join(&file_name))?;
+                image_number += 1;
+                Ok((file_name, image.width(), image.height()))
+            }
+            Err(error) => Err(error.to_string()),
+        };
+        extracted.push(ExtractedRecord {
+            first_sector,
+            last_sector: record[record.len() - 1].sector_index(),
+            channel: record[0].channel_number(),
+            coding: record[0].coding_info().video_string(),
+            palette_sector,
+            result,
+        });
+    }
+
+    fs::write(Path::new(&options.output_dir).join("manifest.json"), manifest_json(&extracted))?;
+    Ok(extracted)
+}
+
+/// Chooses the palettes to load from each sector: all of its CLUT command palettes, or its
+/// most confident palette when it has none.
+fn palette_updates(candidates: Vec<PaletteCandidate>) -> Vec<PaletteCandidate> {
+    let mut updates: Vec<PaletteCandidate> = Vec::new();
+    for candidate in candidates {
+        let sector_start = updates
+            .iter()
+            .rposition(|p| p.sector_index != candidate.sector_index)
+            .map_or(0, |position| position + 1);
+        let sector_updates = &mut updates[sector_start..];
+        let sector_has_clut = sector_updates.iter().any(|p| p.palette_type == CdiPaletteType::Clut);
+        if candidate.palette_type == CdiPaletteType::Clut {
+            if !sector_has_clut {
+                updates.truncate(sector_start);
+            }
+            updates.push(candidate);
+        } else if sector_has_clut {
+            continue;
+        } else if let Some(best) = sector_updates.first_mut() {
+            if candidate.confidence > best.confidence {
+                *best = candidate;
+            }
+        } else {
+            updates.push(candidate);
+        }
+    }
+    updates
+}
+
+/// Formats extracted records as a JSON manifest.
+pub fn manifest_json(records: &[ExtractedRecord]) -> String {
+    let entries: Vec<String> = records
+        .iter()
+        .map(|r| {
+            let palette_sector = r.palette_sector.map_or("null".to_string(), |s| s.to_string());
+            let result = match &r.result {
+                Ok((file_name, width, height)) => {
+                    format!("\"file\": \"{}\", \"width\": {}, \"height\": {}", escape_json(file_name), width, height)
+                }
+                Err(error) => format!("\"error\": \"{}\"", escape_json(error)),
+            };
+            format!(
+                "    {{ \"first_sector\": {}, \"last_sector\": {}, \"channel\": {}, \"coding\": \"{}\", \"palette_sector\": {}, {} }}",
+                r.first_sector, r.last_sector, r.channel, r.coding, palette_sector, result
+            )
+        })
+        .collect();
+    if entries.is_empty() {
+        return "{\n  \"records\": []\n}\n".to_string();
+    }
+    format!("{{\n  \"records\": [\n{}\n  ]\n}}\n", entries.join(",\n"))
+}
+
+fn escape_json(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,198 @@
+use crate::data::cdi_coding_info::CdiCodingInfo;
+use crate::data::CdiVideoType;
+use crate::helpers::image_format_helpers::{decode_dyuv_luminance, DyuvImageConfig};
+
+/// A proposed size for the image held in a video record, with a score from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DimensionCandidate {
+    pub width: u32,
+    pub height: u32,
+    pub score: f32,
+}
+
+const NORMAL_WIDTHS: [u32; 3] = [384, 360, 352];
+const DOUBLE_WIDTHS: [u32; 3] = [768, 720, 704];
+const FIELD_HEIGHTS: [u32; 2] = [240, 280];
+const FRAME_HEIGHTS: [u32; 2] = [480, 560];
+/// The data size of a form 2 video sector, records are padded up to a whole sector.
+const SECTOR_VIDEO_SIZE: usize = 2324;
+/// The number of lines compared when scoring line correlation.
+const CORRELATION_LINES: u32 = 64;
+
+/// Proposes likely dimensions for a video record, best candidate first.
+///
+/// Candidate widths follow the resolution of `coding_info`, 384 wide for normal resolution
+/// and 768 wide for double and high resolution, along with the narrower safe area widths.
+/// Heights are 240 and 280 lines, plus 480 and 560 for high resolution records that
+/// do not hold a single field of odd lines.
+///
+/// Uncompressed codings are scored on how well the record size matches the candidate and
+/// how closely neighbouring lines resemble each other, decoding DYUV records to compare
+/// their luminance. Run-length codings are scored by walking the runs and end of line
+/// markers, and propose the number of lines found rather than a fixed height.
+///
+/// For QHY records the candidates describe the DYUV base plane, as used by `QhyImageConfig`.
+/// MPEG and reserved codings return no candidates.
+pub fn guess_image_dimensions(record: &[u8], coding_info: CdiCodingInfo) -> Vec<DimensionCandidate> {
+    let coding = coding_info.coding();
+    let is_double_width = coding_info.resolution() != 0 && coding != CdiVideoType::QHY as u8;
+    let widths = if is_double_width { DOUBLE_WIDTHS } else { NORMAL_WIDTHS };
+    let mut heights = FIELD_HEIGHTS.to_vec();
+    if coding_info.resolution() == 3 && !coding_info.is_odd_lines() {
+        heights.extend(FRAME_HEIGHTS);
+    }
+
+    let mut candidates = if coding == CdiVideoType::RL3 as u8 || coding == CdiVideoType::RL7 as u8 {
+        guess_rle_dimensions(record, coding == CdiVideoType::RL3 as u8, &widths, &heights)
+    } else if coding <= CdiVideoType::QHY as u8 {
+        guess_fixed_dimensions(record, coding, &widths, &heights)
+    } else {
+        Vec::new()
+    };
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates
+}
+
+fn guess_fixed_dimensions(record: &[u8], coding: u8, widths: &[u32], heights: &[u32]) -> Vec<DimensionCandidate> {
+    let mut candidates = Vec::new();
+    for width in widths {
+        let line_bytes = if coding == CdiVideoType::CLUT4 as u8 { width / 2 } else { *width } as usize;
+        for height in heights {
+            let expected = line_bytes * *height as usize;
+            let size_score = if record.len() >= expected && record.len() - expected < SECTOR_VIDEO_SIZE {
+                1.0
+            } else if record.len() < expected {
+                (record.len() as f32 / expected as f32).powi(2)
+            } else {
+                (expected as f32 / record.len() as f32).powi(2)
+            };
+
+            let lines = std::cmp::min(*height, CORRELATION_LINES);
+            let correlation = if coding == CdiVideoType::DYUV as u8 || coding == CdiVideoType::QHY as u8 {
+                let luminance = decode_dyuv_luminance(DyuvImageConfig {
+                    width: *width,
+                    height: lines,
+                    encoded_data: record.iter().take(line_bytes * lines as usize).cloned().collect(),
+                    initial_y: 16,
+                    initial_u: 128,
+                    initial_v: 128,
+                });
+                match luminance {
+                    Ok(luminance) => line_correlation(&luminance, *width as usize),
+                    Err(_) => 0.0,
+                }
+            } else {
+                let values: Vec<u8> = record.iter().take(line_bytes * lines as usize).cloned().collect();
+                line_correlation(&values, line_bytes)
+            };
+
+            candidates.push(DimensionCandidate {
+                width: *width,
+                height: *height,
+                score: size_score * 0.5 + correlation * 0.5,
+            });
+        }
+    }
+    candidates
+}
+
+/// Scores how similar each line is to the one below it, from 0 to 1.
+fn line_correlation(values: &[u8], line_length: usize) -> f32 {
+    if line_length == 0 || values.len() < line_length * 2 {
+        return 0.0;
+    }
+    let line_count = values.len() / line_length;
+    let total_difference: u64 = (0..(line_count - 1) * line_length)
+        .map(|i| values[i].abs_diff(values[i + line_length]) as u64)
+        .sum();
+    let mean_difference = total_difference as f32 / ((line_count - 1) * line_length) as f32;
+    (1.0 - mean_difference / 64.0).clamp(0.0, 1.0)
+}
+
+enum RleToken {
+    Pixels(usize),
+    EndOfLine,
+}
+
+fn guess_rle_dimensions(record: &[u8], is_rl3: bool, widths: &[u32], heights: &[u32]) -> Vec<DimensionCandidate> {
+    let tokens = read_rle_tokens(record, is_rl3);
+    let mut candidates = Vec::new();
+
+    for width in widths {
+        let width_pixels = *width as usize;
+        let mut pixel_count = 0;
+        let mut line_count = 0;
+        let mut clean_count = 0;
+        for token in &tokens {
+            match token {
+                RleToken::Pixels(count) => {
+                    pixel_count += count;
+                    if pixel_count >= width_pixels {
+                        line_count += 1;
+                        if pixel_count == width_pixels {
+                            clean_count += 1;
+                        }
+                        pixel_count = 0;
+                    }
+                }
+                RleToken::EndOfLine => {
+                    line_count += 1;
+                    clean_count += 1;
+                    pixel_count = 0;
+                }
+            }
+        }
+        if pixel_count > 0 {
+            line_count += 1;
+        }
+        if line_count == 0 {
+            continue;
+        }
+
+        let clean_ratio = clean_count as f32 / line_count as f32;
+        let nearest_height = heights
+            .iter()
+            .min_by_key(|h| h.abs_diff(line_count as u32))
+            .copied()
+            .unwrap_or(line_count as u32);
+        let height_score = if nearest_height.abs_diff(line_count as u32) <= 2 { 1.0 } else { 0.5 };
+        let height = if height_score == 1.0 { nearest_height } else { line_count as u32 };
+
+        candidates.push(DimensionCandidate {
+            width: *width,
+            height,
+            score: clean_ratio * 0.8 + height_score * 0.2,
+        });
+    }
+    candidates
+}
+
+/// Splits run-length data into pixel counts and end of line markers, dropping trailing zero padding.
+fn read_rle_tokens(record: &[u8], is_rl3: bool) -> Vec<RleToken> {
+    let pixels_per_byte = if is_rl3 { 2 } else { 1 };
+    let mut tokens = Vec::new();
+    let mut last_data_token = 0;
+    let mut i = 0;
+    while i < record.len() {
+        if record[i] & 0x80 == 0 {
+            tokens.push(RleToken::Pixels(pixels_per_byte));
+            if record[i] != 0 {
+                last_data_token = tokens.len();
+            }
+            i += 1;
+            continue;
+        }
+        if i + 1 >= record.len() {
+            break;
+        }
+        match record[i + 1] {
+            0 => tokens.push(RleToken::EndOfLine),
+            run_length => tokens.push(RleToken::Pixels(run_length as usize * pixels_per_byte)),
+        }
+        last_data_token = tokens.len();
+        i += 2;
+    }
+    tokens.truncate(last_data_token);
+    tokens
+}
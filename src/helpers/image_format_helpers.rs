@@ -1,12 +1,66 @@
-use std::{fs::File, io::BufWriter, u32};
+use image::{ImageBuffer, Rgba};
 
-use image::{ImageBuffer, Rgba, imageops::FilterType};
-use gif::{Frame, Encoder, Repeat};
+use crate::helpers::animation_helpers::{create_animation_writer, AnimationConfig, AnimationFormat, AnimationFrame};
 
 const DEQUANTIZER_ARRAY: [u8; 16] = [
     0, 1, 4, 9, 16, 27, 44, 79, 128, 177, 212, 229, 240, 247, 252, 255,
 ];
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The coding cannot be decoded to an image, such as MPEG or a reserved value.
+    UnsupportedCoding(u8),
+    /// The record holds no sectors or no data.
+    EmptyRecord,
+    /// A CLUT based coding was decoded without any CLUT colours.
+    MissingClut,
+    /// A coding that spans both planes is missing the sectors of one of them.
+    MissingPlane(String),
+    /// No dimensions were given and none could be guessed from the record.
+    UnknownDimensions,
+    /// Fewer bytes were supplied than the image dimensions require.
+    InsufficientData { expected: usize, actual: usize },
+    /// The dimensions cannot describe an image, such as a line width of 0.
+    InvalidDimensions { width: u32, height: u32 },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnsupportedCoding(coding) => write!(f, "unsupported video coding {coding}"),
+            DecodeError::EmptyRecord => write!(f, "the record holds no data"),
+            DecodeError::MissingClut => write!(f, "no CLUT colours were supplied"),
+            DecodeError::MissingPlane(plane) => write!(f, "missing {plane} sectors"),
+            DecodeError::UnknownDimensions => write!(f, "the image dimensions could not be determined"),
+            DecodeError::InsufficientData { expected, actual } => {
+                write!(f, "expected {expected} bytes of image data, found {actual}")
+            }
+            DecodeError::InvalidDimensions { width, height } => write!(f, "invalid image dimensions {width}x{height}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn check_data_length(data: &[u8], expected: usize) -> Result<(), DecodeError> {
+    if data.len() < expected {
+        return Err(DecodeError::InsufficientData {
+            expected,
+            actual: data.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the number of bytes an image needs at `line_bytes` bytes per line, or an
+/// `InvalidDimensions` error when that overflows.
+fn image_data_length(line_bytes: u32, width: u32, height: u32) -> Result<usize, DecodeError> {
+    line_bytes
+        .checked_mul(height)
+        .map(|length| length as usize)
+        .ok_or(DecodeError::InvalidDimensions { width, height })
+}
+
 pub struct DyuvImageConfig {
     pub width: u32,
     pub height: u32,
@@ -16,15 +70,55 @@ pub struct DyuvImageConfig {
     pub initial_v: u32,
 }
 
-pub fn decode_dyuv_image(config: DyuvImageConfig) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    let mut encoded_index: usize = 0;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DyuvStartValues {
+    pub y: u8,
+    pub u: u8,
+    pub v: u8,
+}
+
+pub fn decode_dyuv_image(config: DyuvImageConfig) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    decode_dyuv_image_with_line_starts(config, &[])
+}
+
+/// Decodes a DYUV image using a separate set of start values for each line.
+///
+/// Lines without an entry in `line_starts` fall back to the initial values of the config.
+pub fn decode_dyuv_image_with_line_starts(config: DyuvImageConfig, line_starts: &[DyuvStartValues]) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    check_data_length(&config.encoded_data, dyuv_data_length(config.width, config.height)?)?;
     let mut decoded_image = ImageBuffer::new(config.width, config.height);
+    decode_dyuv_pixels(&config, line_starts, |x, y, yuv| {
+        decoded_image.put_pixel(x, y, yuv_to_rgb(yuv[0].into(), yuv[1].into(), yuv[2].into()));
+    });
+    Ok(decoded_image)
+}
+
+/// Decodes only the luminance of a DYUV image, one byte per pixel in line order.
+pub fn decode_dyuv_luminance(config: DyuvImageConfig) -> Result<Vec<u8>, DecodeError> {
+    let data_length = dyuv_data_length(config.width, config.height)?;
+    check_data_length(&config.encoded_data, data_length)?;
+    let mut luminance = vec![0; image_data_length(config.width, config.width, config.height)?];
+    decode_dyuv_pixels(&config, &[], |x, y, yuv| {
+        luminance[(y * config.width + x) as usize] = yuv[0];
+    });
+    Ok(luminance)
+}
+
+/// Returns the number of bytes a DYUV image needs, 2 bytes for every pair of pixels.
+fn dyuv_data_length(width: u32, height: u32) -> Result<usize, DecodeError> {
+    image_data_length(width.div_ceil(2).saturating_mul(2), width, height)
+}
+
+/// Walks the DYUV data and passes the reconstructed YUV value of every decoded pixel to `put_pixel`.
+fn decode_dyuv_pixels(config: &DyuvImageConfig, line_starts: &[DyuvStartValues], mut put_pixel: impl FnMut(u32, u32, [u8; 3])) {
+    let mut encoded_index: usize = 0;
     let encoded_data = &config.encoded_data[..];
 
     for y in 0..config.height {
-        let mut prev_y = config.initial_y as u16;
-        let mut prev_u = config.initial_u as u16;
-        let mut prev_v = config.initial_v as u16;
+        let (mut prev_y, mut prev_u, mut prev_v) = match line_starts.get(y as usize) {
+            Some(start) => (start.y as u16, start.u as u16, start.v as u16),
+            None => (config.initial_y as u16, config.initial_u as u16, config.initial_v as u16),
+        };
 
         for x in (0..config.width).step_by(2) {
             if encoded_index >= encoded_data.len() || encoded_index + 1 >= encoded_data.len() {
@@ -50,27 +144,57 @@ pub fn decode_dyuv_image(config: DyuvImageConfig) -> ImageBuffer<Rgba<u8>, Vec<u
             prev_u = uout2 as u16;
             prev_v = vout2 as u16;
 
-            let rgb1 = yuv_to_rgb(yout1.into(), uout1.into(), vout1.into());
-            let rgb2 = yuv_to_rgb(yout2.into(), uout2.into(), vout2.into());
-
-            decoded_image.put_pixel(
-                x,
-                y,
-                Rgba([rgb1[0] as u8, rgb1[1] as u8, rgb1[2] as u8, 0xFF]),
-            );
+            put_pixel(x, y, [yout1, uout1, vout1]);
             if x + 1 < config.width {
-                decoded_image.put_pixel(
-                    x + 1,
-                    y,
-                    Rgba([rgb2[0] as u8, rgb2[1] as u8, rgb2[2] as u8, 0xFF]),
-                );
+                put_pixel(x + 1, y, [yout2, uout2, vout2]);
             }
 
             encoded_index += 2;
         }
     }
+}
 
-    decoded_image
+pub struct QhyImageConfig {
+    pub width: u32,
+    pub height: u32,
+    pub dyuv_data: Vec<u8>,
+    pub qhy_data: Vec<u8>,
+    pub initial_y: u32,
+    pub initial_u: u32,
+    pub initial_v: u32,
+}
+
+/// Decodes a QHY image into a double width RGBA image.
+///
+/// `width` and `height` describe the normal resolution DYUV base plane, which supplies
+/// the chroma and base luminance of each pixel. Every pixel is split into two high
+/// resolution pixels, whose luminance is the base luminance plus the `DEQUANTIZER_ARRAY`
+/// step given by one nibble of the QHY detail plane, high nibble first.
+/// The resulting image is `width * 2` pixels wide, 768 for a normal 384 wide base plane.
+pub fn decode_qhy_image(config: QhyImageConfig) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    let invalid_dimensions = DecodeError::InvalidDimensions { width: config.width, height: config.height };
+    let output_width = config.width.checked_mul(2).ok_or(invalid_dimensions)?;
+    check_data_length(&config.dyuv_data, dyuv_data_length(config.width, config.height)?)?;
+    check_data_length(&config.qhy_data, image_data_length(config.width, config.width, config.height)?)?;
+    let mut decoded_image = ImageBuffer::new(output_width, config.height);
+    let dyuv_config = DyuvImageConfig {
+        width: config.width,
+        height: config.height,
+        encoded_data: config.dyuv_data,
+        initial_y: config.initial_y,
+        initial_u: config.initial_u,
+        initial_v: config.initial_v,
+    };
+    let qhy_data = &config.qhy_data[..];
+
+    decode_dyuv_pixels(&dyuv_config, &[], |x, y, yuv| {
+        let detail = qhy_data[(y * config.width + x) as usize];
+        for (offset, nibble) in [detail >> 4, detail & 0x0F].into_iter().enumerate() {
+            let luminance = yuv[0].wrapping_add(DEQUANTIZER_ARRAY[nibble as usize]);
+            decoded_image.put_pixel(x * 2 + offset as u32, y, yuv_to_rgb(luminance.into(), yuv[1].into(), yuv[2].into()));
+        }
+    });
+    Ok(decoded_image)
 }
 
 fn yuv_to_rgb(y: i32, u: i32, v: i32) -> Rgba<u8> {
@@ -84,6 +208,126 @@ fn clamp(value: i32) -> i32 {
     value.clamp(0, 255)
 }
 
+fn rgb_to_yuv(pixel: &Rgba<u8>) -> (i32, i32, i32) {
+    let r = pixel[0] as i32;
+    let g = pixel[1] as i32;
+    let b = pixel[2] as i32;
+    let y = (77 * r + 150 * g + 29 * b + 128) / 256;
+    let u = clamp(128 + ((b - y) * 256) / 444);
+    let v = clamp(128 + ((r - y) * 256) / 351);
+    (y, u, v)
+}
+
+/// Returns the `DEQUANTIZER_ARRAY` index whose step lands closest to `target` from `previous`.
+fn quantize_dyuv_delta(previous: u8, target: i32) -> u8 {
+    let mut best_index = 0;
+    let mut best_error = i32::MAX;
+    for (index, step) in DEQUANTIZER_ARRAY.iter().enumerate() {
+        let reconstructed = previous.wrapping_add(*step) as i32;
+        let error = (reconstructed - target).abs();
+        if error < best_error {
+            best_error = error;
+            best_index = index as u8;
+        }
+    }
+    best_index
+}
+
+/// Returns the `DEQUANTIZER_ARRAY` index whose chroma step best reconstructs a pixel pair.
+///
+/// The step sets the chroma of the second pixel, and the decoder gives the first pixel the
+/// average of that and `previous`, so the error of both pixels is measured.
+fn quantize_dyuv_chroma_delta(previous: u8, first: i32, second: i32) -> u8 {
+    let mut best_index = 0;
+    let mut best_error = i32::MAX;
+    for (index, step) in DEQUANTIZER_ARRAY.iter().enumerate() {
+        let second_out = previous.wrapping_add(*step) as i32;
+        let first_out = (previous as i32 + second_out) / 2;
+        let error = (first_out - first).abs() + (second_out - second).abs();
+        if error < best_error {
+            best_error = error;
+            best_index = index as u8;
+        }
+    }
+    best_index
+}
+
+pub struct DyuvEncoderConfig {
+    pub image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    pub initial_y: u32,
+    pub initial_u: u32,
+    pub initial_v: u32,
+    pub use_line_start_values: bool,
+}
+
+pub struct DyuvEncodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub encoded_data: Vec<u8>,
+    pub line_start_values: Vec<DyuvStartValues>,
+}
+
+/// Encodes an RGBA image as DYUV data.
+///
+/// Each pair of pixels is encoded as a `u dy1 v dy2` word by picking the closest
+/// `DEQUANTIZER_ARRAY` step. The encoder tracks the values the decoder will reconstruct
+/// rather than the source values, so quantisation errors are fed back into the next
+/// delta instead of accumulating along the line. When `use_line_start_values` is set,
+/// each line starts from the colour of its first pixel, otherwise every line starts
+/// from the configured initial values.
+pub fn encode_dyuv_image(config: DyuvEncoderConfig) -> DyuvEncodedImage {
+    let (width, height) = config.image.dimensions();
+    let mut encoded_data = Vec::with_capacity((width.div_ceil(2) * 2 * height) as usize);
+    let mut line_start_values = Vec::with_capacity(height as usize);
+
+    for y in 0..height {
+        let start = if config.use_line_start_values && width > 0 {
+            let (sy, su, sv) = rgb_to_yuv(config.image.get_pixel(0, y));
+            DyuvStartValues { y: sy as u8, u: su as u8, v: sv as u8 }
+        } else {
+            DyuvStartValues {
+                y: config.initial_y as u8,
+                u: config.initial_u as u8,
+                v: config.initial_v as u8,
+            }
+        };
+        line_start_values.push(start);
+
+        let mut prev_y = start.y;
+        let mut prev_u = start.u;
+        let mut prev_v = start.v;
+
+        for x in (0..width).step_by(2) {
+            let (y1, u1, v1) = rgb_to_yuv(config.image.get_pixel(x, y));
+            let (y2, u2, v2) = if x + 1 < width {
+                rgb_to_yuv(config.image.get_pixel(x + 1, y))
+            } else {
+                (y1, u1, v1)
+            };
+
+            let du = quantize_dyuv_chroma_delta(prev_u, u1, u2);
+            let dv = quantize_dyuv_chroma_delta(prev_v, v1, v2);
+            let dy1 = quantize_dyuv_delta(prev_y, y1);
+            let yout1 = prev_y.wrapping_add(DEQUANTIZER_ARRAY[dy1 as usize]);
+            let dy2 = quantize_dyuv_delta(yout1, y2);
+
+            prev_y = yout1.wrapping_add(DEQUANTIZER_ARRAY[dy2 as usize]);
+            prev_u = prev_u.wrapping_add(DEQUANTIZER_ARRAY[du as usize]);
+            prev_v = prev_v.wrapping_add(DEQUANTIZER_ARRAY[dv as usize]);
+
+            encoded_data.push((du << 4) | dy1);
+            encoded_data.push((dv << 4) | dy2);
+        }
+    }
+
+    DyuvEncodedImage {
+        width,
+        height,
+        encoded_data,
+        line_start_values,
+    }
+}
+
 pub struct Clut7Config {
     pub width: u32,
     pub height: u32,
@@ -94,39 +338,152 @@ pub struct Clut7Config {
     pub use_lower_indexes: bool,
 }
 
-pub fn decode_clut7_image(config: Clut7Config) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+pub fn decode_clut7_image(config: Clut7Config) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    if config.clut_data.is_empty() {
+        return Err(DecodeError::MissingClut);
+    }
+    check_data_length(&config.encoded_data, image_data_length(config.width, config.width, config.height)?)?;
     let mut decoded_image = ImageBuffer::new(config.width, config.height);
     let encoded_data = &config.encoded_data[..];
+
+    for y in 0..config.height {
+        for x in 0..config.width {
+            let index = (y * config.width + x) as usize;
+            let color = clut_pixel_color(&config, encoded_data[index] as usize);
+            decoded_image.put_pixel(x, y, color);
+        }
+    }
+    Ok(decoded_image)
+}
+
+fn clut_pixel_color(config: &Clut7Config, clut_index: usize) -> Rgba<u8> {
     let clut_data = &config.clut_data[..];
+    if clut_index < clut_data.len() {
+        if config.use_transparency
+            && ((config.use_lower_indexes
+                && clut_index <= config.transparency_index as usize)
+                || (!config.use_lower_indexes
+                    && clut_index >= config.transparency_index as usize)
+                || clut_index == 0)
+        {
+            Rgba([0, 0, 0, 0])
+        } else {
+            clut_data[clut_index]
+        }
+    } else {
+        clut_data[clut_index % clut_data.len()]
+    }
+}
+
+/// CLUT8 images use one byte per pixel with the full 256 entry CLUT,
+/// and share the transparency options of CLUT7.
+pub type Clut8Config = Clut7Config;
+
+pub fn decode_clut8_image(config: Clut8Config) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    decode_clut7_image(config)
+}
+
+pub struct Clut4Config {
+    pub width: u32,
+    pub height: u32,
+    pub encoded_data: Vec<u8>,
+    pub clut_data: Vec<Rgba<u8>>,
+    pub use_transparency: bool,
+    pub transparency_index: u8,
+    pub use_lower_indexes: bool,
+    pub high_nibble_first: bool,
+}
+
+/// Decodes a CLUT4 image, where each byte holds two 4-bit CLUT indexes.
+///
+/// `width` is in pixels, so double resolution images are decoded with a width of 768.
+/// Each line occupies `width / 2` bytes, rounded up for odd widths, and `high_nibble_first`
+/// selects which nibble of a byte is the left pixel.
+pub fn decode_clut4_image(config: Clut4Config) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    if config.clut_data.is_empty() {
+        return Err(DecodeError::MissingClut);
+    }
+    let line_bytes = config.width.div_ceil(2);
+    check_data_length(&config.encoded_data, image_data_length(line_bytes, config.width, config.height)?)?;
+    let mut decoded_image = ImageBuffer::new(config.width, config.height);
+    let clut_config = Clut7Config {
+        width: config.width,
+        height: config.height,
+        encoded_data: Vec::new(),
+        clut_data: config.clut_data,
+        use_transparency: config.use_transparency,
+        transparency_index: config.transparency_index,
+        use_lower_indexes: config.use_lower_indexes,
+    };
+
+    for y in 0..config.height {
+        for x in 0..config.width {
+            let byte = config.encoded_data[(y * line_bytes + x / 2) as usize];
+            let is_left_pixel = x % 2 == 0;
+            let clut_index = if is_left_pixel == config.high_nibble_first {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+            let color = clut_pixel_color(&clut_config, clut_index as usize);
+            decoded_image.put_pixel(x, y, color);
+        }
+    }
+    Ok(decoded_image)
+}
+
+pub struct Rgb555Config {
+    pub width: u32,
+    pub height: u32,
+    pub lower_data: Vec<u8>,
+    pub upper_data: Vec<u8>,
+    pub use_transparency: bool,
+    pub transparent_bit_set: bool,
+}
+
+/// Decodes an RGB555 image from its lower and upper halves.
+///
+/// Each pixel is a 16-bit `TRRRRRGGGGGBBBBB` value, with the upper byte stored in the
+/// RGB555H sectors and the lower byte in the RGB555L sectors. When `use_transparency`
+/// is set, pixels whose T bit equals `transparent_bit_set` are fully transparent.
+pub fn decode_rgb555_image(config: Rgb555Config) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    let pixel_count = image_data_length(config.width, config.width, config.height)?;
+    check_data_length(&config.lower_data, pixel_count)?;
+    check_data_length(&config.upper_data, pixel_count)?;
+    let mut decoded_image = ImageBuffer::new(config.width, config.height);
 
     for y in 0..config.height {
         for x in 0..config.width {
             let index = (y * config.width + x) as usize;
-            let clut_index = encoded_data[index] as usize;
-            let color = if clut_index < clut_data.len() {
-                if config.use_transparency
-                    && ((config.use_lower_indexes
-                        && clut_index <= config.transparency_index as usize)
-                        || (!config.use_lower_indexes
-                            && clut_index >= config.transparency_index as usize)
-                        || clut_index == 0)
-                {
-                    Rgba([0, 0, 0, 0])
-                } else {
-                    clut_data[clut_index]
-                }
+            let pixel = ((config.upper_data[index] as u16) << 8) | config.lower_data[index] as u16;
+            let transparency_bit = pixel & 0x8000 != 0;
+            let color = if config.use_transparency && transparency_bit == config.transparent_bit_set {
+                Rgba([0, 0, 0, 0])
             } else {
-                clut_data[clut_index % clut_data.len()]
+                Rgba([
+                    expand_5_bit(pixel >> 10),
+                    expand_5_bit(pixel >> 5),
+                    expand_5_bit(pixel),
+                    0xFF,
+                ])
             };
             decoded_image.put_pixel(x, y, color);
         }
     }
-    decoded_image
+    Ok(decoded_image)
+}
+
+fn expand_5_bit(value: u16) -> u8 {
+    let value = (value & 0x1F) as u8;
+    (value << 3) | (value >> 2)
 }
 
 pub fn decode_rle_bytes(rle_data: &[u8], line_width: usize) -> Vec<u8> {
     let mut lines = Vec::new();
     let mut current_line = Vec::new();
+    if line_width == 0 {
+        return lines;
+    }
 
     let mut i = 0;
     while i < rle_data.len() {
@@ -153,7 +510,7 @@ pub fn decode_rle_bytes(rle_data: &[u8], line_width: usize) -> Vec<u8> {
             };
 
             let actual_add_length = std::cmp::min(add_length, line_width - current_line.len());
-            current_line.extend(std::iter::repeat(color_index).take(actual_add_length));
+            current_line.extend(std::iter::repeat_n(color_index, actual_add_length));
         } else {
             // Single pixel
             current_line.push(color_index);
@@ -161,7 +518,7 @@ pub fn decode_rle_bytes(rle_data: &[u8], line_width: usize) -> Vec<u8> {
         }
 
         if current_line.len() == line_width {
-            lines.extend(current_line.drain(..));
+            lines.append(&mut current_line);
         }
     }
 
@@ -173,6 +530,121 @@ pub fn decode_rle_bytes(rle_data: &[u8], line_width: usize) -> Vec<u8> {
     lines
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RleLineStatus {
+    /// The line decoded to exactly `line_width` pixels.
+    Clean,
+    /// The data ran out before the line was complete, the rest was padded with index 0.
+    Padded,
+    /// A run overflowed the line, the excess was dropped and decoding resumed at the next line.
+    Truncated,
+}
+
+pub struct RleDecodeResult {
+    pub pixels: Vec<u8>,
+    pub line_width: usize,
+    pub line_offsets: Vec<usize>,
+    pub line_status: Vec<RleLineStatus>,
+}
+
+impl RleDecodeResult {
+    pub fn line_count(&self) -> usize {
+        self.line_status.len()
+    }
+
+    pub fn clean_line_count(&self) -> usize {
+        self.line_status.iter().filter(|s| **s == RleLineStatus::Clean).count()
+    }
+
+    pub fn line(&self, index: usize) -> &[u8] {
+        &self.pixels[index * self.line_width..(index + 1) * self.line_width]
+    }
+}
+
+/// Decodes RL7 data line by line, recovering from corrupt lines.
+///
+/// Unlike `decode_rle_bytes`, every line is tracked separately: a run length of 0 fills
+/// the rest of the line, a run length of 1 is a single pixel, and a run that overflows the
+/// line is truncated, after which decoding resynchronises on the next end of line run
+/// so the damage stays within one line. Short lines are padded with index 0.
+///
+/// When `height` is 0, lines are decoded until the data runs out, otherwise exactly
+/// `height` lines are returned. `line_offsets` holds the byte offset each line starts at.
+pub fn decode_rl7_lines(rle_data: &[u8], line_width: usize, height: usize) -> RleDecodeResult {
+    let mut result = RleDecodeResult {
+        pixels: Vec::new(),
+        line_width,
+        line_offsets: Vec::new(),
+        line_status: Vec::new(),
+    };
+    if line_width == 0 {
+        return result;
+    }
+
+    let mut i = 0;
+    while (height == 0 && i < rle_data.len()) || (height != 0 && result.line_count() < height) {
+        let line_start = result.pixels.len();
+        result.line_offsets.push(i);
+        let mut status = RleLineStatus::Clean;
+
+        while result.pixels.len() - line_start < line_width {
+            if i >= rle_data.len() {
+                status = RleLineStatus::Padded;
+                break;
+            }
+
+            let first_byte = rle_data[i];
+            let color_index = first_byte & 0x7F;
+            let remaining = line_width - (result.pixels.len() - line_start);
+
+            if first_byte & 0x80 == 0 {
+                result.pixels.push(color_index);
+                i += 1;
+                continue;
+            }
+
+            if i + 1 >= rle_data.len() {
+                i = rle_data.len();
+                status = RleLineStatus::Padded;
+                break;
+            }
+
+            let run_length = rle_data[i + 1] as usize;
+            i += 2;
+            if run_length == 0 || run_length <= remaining {
+                let length = if run_length == 0 { remaining } else { run_length };
+                result.pixels.extend(std::iter::repeat_n(color_index, length));
+            } else {
+                result.pixels.extend(std::iter::repeat_n(color_index, remaining));
+                status = RleLineStatus::Truncated;
+                i = find_rl7_line_end(rle_data, i, line_width * 2).unwrap_or(i);
+            }
+        }
+
+        result.pixels.resize(line_start + line_width, 0);
+        result.line_status.push(status);
+    }
+
+    result
+}
+
+/// Returns the offset just after the next end of line run, searching at most `limit` bytes.
+fn find_rl7_line_end(rle_data: &[u8], start: usize, limit: usize) -> Option<usize> {
+    let end = std::cmp::min(rle_data.len(), start + limit);
+    let mut i = start;
+    while i + 1 < end {
+        if rle_data[i] & 0x80 != 0 {
+            if rle_data[i + 1] == 0 {
+                return Some(i + 2);
+            }
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
 pub struct RleImageConfig {
     pub encoded_data: Vec<u8>,
     pub line_width: usize,
@@ -181,18 +653,27 @@ pub struct RleImageConfig {
     pub use_transparency: bool,
 }
 
-pub fn decode_rle_image(config: RleImageConfig) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-  let image_bytes = decode_rle_bytes(&config.encoded_data, config.line_width);
+pub fn decode_rle_image(config: RleImageConfig) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+  if config.line_width == 0 {
+    return Err(DecodeError::InvalidDimensions { width: 0, height: config.height as u32 });
+  }
+  rle_pixel_count(config.line_width, config.height)?;
+  let image_bytes = decode_rl7_lines(&config.encoded_data, config.line_width, config.height).pixels;
+  decode_rle_indexes(image_bytes, config)
+}
+
+fn decode_rle_indexes(mut image_bytes: Vec<u8>, config: RleImageConfig) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
   let height = if config.height == 0 {
-    image_bytes.len() / config.line_width
+    image_bytes.len().div_ceil(config.line_width)
   } else {
     config.height
   };
+  image_bytes.resize(rle_pixel_count(config.line_width, height)?, 0);
   let clut_config = Clut7Config {
     width: config.line_width as u32,
     height: height as u32,
     encoded_data: image_bytes,
-    clut_data: config.clut_data.clone(),
+    clut_data: config.clut_data,
     use_transparency: config.use_transparency,
     transparency_index: 0,
     use_lower_indexes: false,
@@ -200,18 +681,174 @@ pub fn decode_rle_image(config: RleImageConfig) -> ImageBuffer<Rgba<u8>, Vec<u8>
   decode_clut7_image(clut_config)
 }
 
-pub fn create_gif(images: Vec<ImageBuffer<Rgba<u8>, Vec<u8>>>, path: &str, width: u16, height: u16) -> Result<(), Box<dyn std::error::Error>> {
-    let mut image_output = File::create(path)?;
-    let mut encoder = Encoder::new(BufWriter::new(&mut image_output), width, height, &[])?;
-    encoder.set_repeat(Repeat::Infinite)?;
+/// Returns the number of pixels of a run-length image, which must fit its u32 dimensions.
+fn rle_pixel_count(line_width: usize, height: usize) -> Result<usize, DecodeError> {
+  u32::try_from(line_width)
+    .ok()
+    .zip(u32::try_from(height).ok())
+    .and_then(|(width, height)| width.checked_mul(height))
+    .map(|count| count as usize)
+    .ok_or(DecodeError::InvalidDimensions { width: line_width as u32, height: height as u32 })
+}
 
-    for image in images {
-        let resized = image::imageops::resize(&image, width.into(), height.into(), FilterType::Nearest);
-        
-        let mut frame = Frame::from_rgba_speed(width, height, &mut resized.into_raw(), 10);
-        frame.delay = 10; // Set the delay between frames (in 1/100th of a second)
-        encoder.write_frame(&frame)?;
+/// Decodes RL3 data into indexed pixels.
+///
+/// Each byte holds a pair of 3-bit colour indexes in bits 6-4 and 2-0. When the MSB is set,
+/// the following byte is the number of times the pair is repeated, with 0 repeating the
+/// pair to the end of the line.
+pub fn decode_rl3_bytes(rle_data: &[u8], line_width: usize) -> Vec<u8> {
+    let mut lines = Vec::new();
+    let mut current_line = Vec::new();
+
+    let mut i = 0;
+    while i < rle_data.len() {
+        let first_byte = rle_data[i];
+        let is_run = (first_byte & 0x80) != 0;
+        let pair = [(first_byte >> 4) & 0x07, first_byte & 0x07];
+
+        let pair_count = if is_run {
+            if i + 1 >= rle_data.len() {
+                break;
+            }
+            let run_length = rle_data[i + 1] as usize;
+            i += 2;
+            if run_length == 0 {
+                (line_width - current_line.len()).div_ceil(2)
+            } else {
+                run_length
+            }
+        } else {
+            i += 1;
+            1
+        };
+
+        let remaining = line_width - current_line.len();
+        current_line.extend(pair.iter().cycle().take(std::cmp::min(pair_count * 2, remaining)));
+
+        if current_line.len() == line_width {
+            lines.append(&mut current_line);
+        }
     }
 
-    Ok(())
+    if !current_line.is_empty() {
+        lines.append(&mut current_line);
+    }
+
+    lines
+}
+
+pub fn decode_rl3_image(config: RleImageConfig) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    if config.line_width == 0 {
+        return Err(DecodeError::InvalidDimensions { width: 0, height: config.height as u32 });
+    }
+    rle_pixel_count(config.line_width, config.height)?;
+    let image_bytes = decode_rl3_bytes(&config.encoded_data, config.line_width);
+    decode_rle_indexes(image_bytes, config)
+}
+
+/// Encodes indexed pixels as RL7 data.
+///
+/// Each line is split into runs of equal colour. Single pixels are written as one byte,
+/// longer runs as a colour and length pair, and the last run of every line uses a length
+/// of 0 so it fills to the end of the line. Runs of length 1 are never written, as
+/// `decode_rle_bytes` skips them. Colour indexes are masked to 7 bits.
+pub fn encode_rl7_bytes(pixels: &[u8], line_width: usize) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    if line_width == 0 {
+        return encoded;
+    }
+
+    for line in pixels.chunks(line_width) {
+        let runs = split_runs(line);
+        for (run_index, (color, length)) in runs.iter().enumerate() {
+            let color = color & 0x7F;
+            let is_last_run = run_index == runs.len() - 1 && line.len() == line_width;
+            encode_run(&mut encoded, color, *length, is_last_run);
+        }
+    }
+
+    encoded
+}
+
+/// Encodes indexed pixels as RL3 data.
+///
+/// Pixels are grouped in pairs, with odd width lines padded by repeating their last pixel,
+/// and each pair is packed into bits 6-4 and 2-0 of a byte. Repeated pairs are written as
+/// runs, with the last run of every line using a length of 0. Colour indexes are masked to 3 bits.
+pub fn encode_rl3_bytes(pixels: &[u8], line_width: usize) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    if line_width == 0 {
+        return encoded;
+    }
+
+    for line in pixels.chunks(line_width) {
+        let pairs: Vec<u8> = line
+            .chunks(2)
+            .map(|pair| {
+                let left = pair[0] & 0x07;
+                let right = pair.get(1).map_or(left, |p| p & 0x07);
+                (left << 4) | right
+            })
+            .collect();
+        let runs = split_runs(&pairs);
+        for (run_index, (pair, length)) in runs.iter().enumerate() {
+            let is_last_run = run_index == runs.len() - 1 && line.len() == line_width;
+            encode_run(&mut encoded, *pair, *length, is_last_run);
+        }
+    }
+
+    encoded
+}
+
+fn split_runs(values: &[u8]) -> Vec<(u8, usize)> {
+    let mut runs: Vec<(u8, usize)> = Vec::new();
+    for value in values {
+        match runs.last_mut() {
+            Some((color, length)) if color == value => *length += 1,
+            _ => runs.push((*value, 1)),
+        }
+    }
+    runs
+}
+
+fn encode_run(encoded: &mut Vec<u8>, value: u8, length: usize, is_last_run: bool) {
+    if is_last_run && length > 1 {
+        encoded.extend([value | 0x80, 0]);
+        return;
+    }
+
+    let mut remaining = length;
+    while remaining > 0 {
+        match remaining {
+            1 | 2 => {
+                encoded.extend(std::iter::repeat_n(value, remaining));
+                remaining = 0;
+            }
+            _ => {
+                let run_length = std::cmp::min(remaining, 255);
+                encoded.extend([value | 0x80, run_length as u8]);
+                remaining -= run_length;
+            }
+        }
+    }
+}
+
+/// Writes images to a looping GIF, showing each for 100ms.
+///
+/// Images that are not `width` by `height` are resized, see `write_animation` for other
+/// formats and per-frame delays.
+pub fn create_gif(images: Vec<ImageBuffer<Rgba<u8>, Vec<u8>>>, path: &str, width: u16, height: u16) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = create_animation_writer(
+        path,
+        AnimationConfig {
+            format: AnimationFormat::Gif,
+            width: width.into(),
+            height: height.into(),
+            loop_count: 0,
+        },
+    )?;
+    for image in images {
+        writer.write_frame(&AnimationFrame { image, delay_ms: 100 })?;
+    }
+    writer.finish()
 }
@@ -0,0 +1,91 @@
+use image::{ImageBuffer, Rgba};
+
+use crate::data::cdi_sector::CdiSector;
+use crate::helpers::decode_helpers::{decode_video_record, DecodeContext};
+use crate::helpers::image_format_helpers::DecodeError;
+use crate::helpers::resolution_helpers::repeat_pixels;
+
+/// The even and odd line records that make up one interlaced frame of a channel.
+///
+/// Either field may be missing when the other has no partner in the file.
+pub struct FieldPair<'a> {
+    pub channel: u8,
+    pub even: Option<Vec<&'a CdiSector>>,
+    pub odd: Option<Vec<&'a CdiSector>>,
+}
+
+/// Pairs even and odd line records into frames, in the order they are triggered on each channel.
+///
+/// Each record is assigned to the channel and field of its first video sector. A field waits
+/// for the next record of the opposite field on the same channel; when another record of the
+/// same field arrives first, the waiting field is returned on its own.
+pub fn pair_field_records<'a>(records: &[Vec<&'a CdiSector>]) -> Vec<FieldPair<'a>> {
+    let mut pending: Vec<FieldPair<'a>> = Vec::new();
+    let mut pairs = Vec::new();
+
+    for record in records {
+        let Some(first_sector) = record.iter().find(|s| s.submode().is_video()) else {
+            continue;
+        };
+        let channel = first_sector.channel_number();
+        let is_odd = first_sector.coding_info().is_odd_lines();
+
+        if let Some(position) = pending.iter().position(|p| p.channel == channel) {
+            let waiting_is_odd = pending[position].odd.is_some();
+            if waiting_is_odd == is_odd {
+                pairs.push(pending.remove(position));
+            } else {
+                let mut pair = pending.remove(position);
+                if is_odd {
+                    pair.odd = Some(record.clone());
+                } else {
+                    pair.even = Some(record.clone());
+                }
+                pairs.push(pair);
+                continue;
+            }
+        }
+
+        pending.push(FieldPair {
+            channel,
+            even: if is_odd { None } else { Some(record.clone()) },
+            odd: if is_odd { Some(record.clone()) } else { None },
+        });
+    }
+
+    pairs.extend(pending);
+    pairs
+}
+
+/// Interleaves an even and an odd field into a full height frame.
+///
+/// Frame lines are numbered from 0, so the even field fills lines 0, 2, 4 and so on and
+/// the odd field fills the lines between them. When only one field is given its lines are
+/// doubled instead. Both fields must have the same dimensions.
+pub fn weave_fields(
+    even: Option<&ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    odd: Option<&ImageBuffer<Rgba<u8>, Vec<u8>>>,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    match (even, odd) {
+        (Some(even), Some(odd)) => {
+            if even.dimensions() != odd.dimensions() {
+                return Err(DecodeError::InvalidDimensions { width: odd.width(), height: odd.height() });
+            }
+            Ok(ImageBuffer::from_fn(even.width(), even.height() * 2, |x, y| {
+                let field = if y % 2 == 0 { even } else { odd };
+                *field.get_pixel(x, y / 2)
+            }))
+        }
+        (Some(field), None) | (None, Some(field)) => Ok(repeat_pixels(field, 1, 2)),
+        (None, None) => Err(DecodeError::EmptyRecord),
+    }
+}
+
+/// Decodes both fields of a pair and weaves them into a frame.
+///
+/// The context dimensions, when set, are those of a single field.
+pub fn decode_field_pair(pair: &FieldPair, context: &DecodeContext) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, DecodeError> {
+    let even = pair.even.as_ref().map(|r| decode_video_record(r, context)).transpose()?;
+    let odd = pair.odd.as_ref().map(|r| decode_video_record(r, context)).transpose()?;
+    weave_fields(even.as_ref(), odd.as_ref())
+}
@@ -2,3 +2,17 @@ pub mod audio_helpers;
 pub mod image_format_helpers;
 pub mod color_helpers;
 pub mod bit_manipulation_helpers;
+pub mod quantization_helpers;
+pub mod palette_discovery_helpers;
+pub mod image_analysis_helpers;
+pub mod decode_helpers;
+pub mod compositing_helpers;
+pub mod display_control_helpers;
+pub mod resolution_helpers;
+pub mod interlace_helpers;
+pub mod animation_helpers;
+pub mod timing_helpers;
+pub mod video_export_helpers;
+pub mod mpeg_helpers;
+pub mod sprite_sheet_helpers;
+pub mod extraction_helpers;
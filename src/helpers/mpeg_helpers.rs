@@ -0,0 +1,209 @@
+use std::fs;
+
+use crate::data::cdi_file::CdiFile;
+use crate::data::cdi_sector::CdiSector;
+use crate::data::CdiVideoType;
+
+const PACK_START_CODE: [u8; 4] = [0x00, 0x00, 0x01, 0xBA];
+const PROGRAM_END_CODE: [u8; 4] = [0x00, 0x00, 0x01, 0xB9];
+const SYSTEM_HEADER_ID: u8 = 0xBB;
+
+/// A run of sectors missing from the file, found from the addresses in the sector headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MpegGap {
+    /// The index of the last sector read before the gap.
+    pub after_sector_index: u32,
+    pub missing_sectors: u32,
+}
+
+/// The streams recovered from MPEG sectors.
+///
+/// `program_stream` is only filled when the sectors hold program stream packs, as on
+/// Digital Video discs and Video CDs, and is empty for sectors holding elementary streams.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MpegDemuxResult {
+    pub video: Vec<u8>,
+    pub audio: Vec<u8>,
+    pub program_stream: Vec<u8>,
+    pub video_sectors: usize,
+    pub audio_sectors: usize,
+    pub gaps: Vec<MpegGap>,
+}
+
+/// Returns whether a sector holds MPEG video or MPEG audio.
+pub fn is_mpeg_sector(sector: &CdiSector) -> bool {
+    let submode = sector.submode();
+    (submode.is_video() || submode.is_audio()) && sector.coding_info().coding() == CdiVideoType::MPEG as u8
+}
+
+/// Collects the MPEG sectors of a file, optionally only those of one channel, and demuxes them.
+pub fn demux_mpeg(file: &CdiFile, channel: Option<u8>) -> MpegDemuxResult {
+    let sectors: Vec<&CdiSector> = file
+        .sectors()
+        .iter()
+        .filter(|s| is_mpeg_sector(s) && channel.is_none_or(|c| s.channel_number() == c))
+        .collect();
+    demux_mpeg_sectors(&sectors)
+}
+
+/// Strips the sector framing from MPEG sectors and separates their video and audio.
+///
+/// Sectors starting with a pack header are parsed as an MPEG-1 or MPEG-2 program stream:
+/// their packs are appended to `program_stream` and the payloads of the first video and
+/// first audio stream are appended to the elementary streams. Other sectors are taken to
+/// hold elementary stream data, which is appended according to the sector type without the
+/// zero padding at the end of the sector.
+///
+/// A gap is reported wherever the header addresses of consecutive sectors advance further
+/// than their positions in the file.
+pub fn demux_mpeg_sectors(sectors: &[&CdiSector]) -> MpegDemuxResult {
+    let mut result = MpegDemuxResult::default();
+    let mut video_stream_id = None;
+    let mut audio_stream_id = None;
+    let mut previous: Option<(u32, u32)> = None;
+
+    for sector in sectors {
+        if let Some(address) = sector.header_address() {
+            if let Some((previous_index, previous_address)) = previous {
+                let index_step = sector.sector_index().saturating_sub(previous_index);
+                let address_step = address.saturating_sub(previous_address);
+                if address_step > index_step {
+                    result.gaps.push(MpegGap {
+                        after_sector_index: previous_index,
+                        missing_sectors: address_step - index_step,
+                    });
+                }
+            }
+            previous = Some((sector.sector_index(), address));
+        }
+
+        let is_video = sector.submode().is_video();
+        if is_video {
+            result.video_sectors += 1;
+        } else {
+            result.audio_sectors += 1;
+        }
+
+        let data = sector.get_form2_data();
+        if data.starts_with(&PACK_START_CODE) {
+            let packs_end = read_packs(&data, &mut |stream_id, payload| {
+                if (0xE0..=0xEF).contains(&stream_id) && *video_stream_id.get_or_insert(stream_id) == stream_id {
+                    result.video.extend_from_slice(payload);
+                } else if (0xC0..=0xDF).contains(&stream_id) && *audio_stream_id.get_or_insert(stream_id) == stream_id {
+                    result.audio.extend_from_slice(payload);
+                }
+            });
+            result.program_stream.extend_from_slice(&data[..packs_end]);
+        } else if is_video {
+            result.video.extend_from_slice(trim_zero_padding(&data));
+        } else {
+            result.audio.extend_from_slice(trim_zero_padding(&data));
+        }
+    }
+
+    if !result.program_stream.is_empty() {
+        result.program_stream.extend_from_slice(&PROGRAM_END_CODE);
+    }
+    result
+}
+
+/// Removes the zeros at the end of elementary stream data, except for two.
+///
+/// The two zeros kept may be the start of a start code that continues in the next sector, and
+/// are otherwise allowed as stuffing before the next start code.
+fn trim_zero_padding(data: &[u8]) -> &[u8] {
+    let end = data.iter().rposition(|byte| *byte != 0).map_or(0, |position| position + 1);
+    &data[..(end + 2).min(data.len())]
+}
+
+/// Walks the packs and packets of program stream data, passing the payload of each PES packet
+/// to `on_payload`, and returns where the last complete pack or packet ends.
+fn read_packs(data: &[u8], on_payload: &mut impl FnMut(u8, &[u8])) -> usize {
+    let mut position = 0;
+    let mut end = 0;
+    while position + 4 <= data.len() {
+        if data[position..position + 3] != [0x00, 0x00, 0x01] {
+            break;
+        }
+        let stream_id = data[position + 3];
+        let next = match stream_id {
+            0xBA => pack_header_length(&data[position..]).map(|length| position + length),
+            0xB9 => Some(position + 4),
+            id if id >= SYSTEM_HEADER_ID => {
+                let Some(length) = data.get(position + 4..position + 6) else {
+                    break;
+                };
+                let packet_end = position + 6 + u16::from_be_bytes([length[0], length[1]]) as usize;
+                if packet_end > data.len() {
+                    break;
+                }
+                if let Some(payload_start) = pes_payload_start(&data[position..packet_end], id) {
+                    on_payload(id, &data[position + payload_start..packet_end]);
+                }
+                Some(packet_end)
+            }
+            _ => None,
+        };
+        match next {
+            Some(next) if next <= data.len() => {
+                position = next;
+                end = next;
+            }
+            _ => break,
+        }
+    }
+    end
+}
+
+/// Returns the length of a pack header, MPEG-1 headers being marked by `0010` and MPEG-2 by `01`.
+fn pack_header_length(data: &[u8]) -> Option<usize> {
+    let marker = *data.get(4)?;
+    if marker >> 4 == 0b0010 {
+        Some(12)
+    } else if marker >> 6 == 0b01 {
+        Some(14 + (*data.get(13)? & 0b111) as usize)
+    } else {
+        None
+    }
+}
+
+/// Returns the offset of the payload within a PES packet, or `None` for packets that are
+/// not audio or video or whose header is malformed.
+fn pes_payload_start(packet: &[u8], stream_id: u8) -> Option<usize> {
+    if !(0xC0..=0xEF).contains(&stream_id) {
+        return None;
+    }
+    let mut position = 6;
+    if *packet.get(position)? >> 6 == 0b10 {
+        // MPEG-2 header with its own length
+        return Some(position + 3 + *packet.get(position + 2)? as usize).filter(|start| *start <= packet.len());
+    }
+    while *packet.get(position)? == 0xFF {
+        position += 1;
+    }
+    if *packet.get(position)? >> 6 == 0b01 {
+        position += 2;
+    }
+    let timestamps = *packet.get(position)? >> 4;
+    position += match timestamps {
+        0b0010 => 5,
+        0b0011 => 10,
+        _ => 1,
+    };
+    Some(position).filter(|start| *start <= packet.len())
+}
+
+/// Writes the non-empty streams next to `base_path`, as `.m1v` video, `.mp2` audio and an
+/// `.mpg` program stream, and returns the paths written.
+pub fn write_mpeg_streams(result: &MpegDemuxResult, base_path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    for (data, extension) in [(&result.video, "m1v"), (&result.audio, "mp2"), (&result.program_stream, "mpg")] {
+        if data.is_empty() {
+            continue;
+        }
+        let path = format!("{}.{}", base_path, extension);
+        fs::write(&path, data)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+
+use image::Rgba;
+
+use crate::data::cdi_file::CdiFile;
+use crate::data::CdiPaletteType;
+use crate::helpers::color_helpers::{read_indexed_palette, read_unindexed_palette, ClutState};
+
+/// A block of sector data that is likely to hold a palette.
+pub struct PaletteCandidate {
+    pub sector_index: u32,
+    pub offset: usize,
+    pub length: usize,
+    pub palette_type: CdiPaletteType,
+    pub confidence: f32,
+    /// The colours in the order they are loaded, the first going to `start_index`.
+    pub colors: Vec<Rgba<u8>>,
+    /// The CLUT index of the first colour, which for CLUT commands includes the selected bank.
+    pub start_index: usize,
+    /// The commands of a CLUT palette, bank select included, so they can be replayed onto a
+    /// `ClutState`. Empty for other palette types.
+    pub commands: Vec<u8>,
+}
+
+/// The minimum number of consecutive entries for CLUT command and indexed palettes.
+const MIN_ENTRY_RUN: usize = 16;
+/// The sizes of unindexed RGB blocks that are checked, 256 and 128 colours.
+const UNINDEXED_BLOCK_SIZES: [usize; 2] = [768, 384];
+/// Unindexed blocks are only looked for near the start of the data, after a small header.
+const MAX_UNINDEXED_OFFSET: usize = 64;
+
+/// Scans every data sector of a file for palettes.
+///
+/// Returns all candidates with a confidence of at least `min_confidence`, in sector order.
+pub fn find_palette_candidates(file: &CdiFile, min_confidence: f32) -> Vec<PaletteCandidate> {
+    let mut candidates = Vec::new();
+    for sector in file.get_data_sectors() {
+        let data = sector.get_sector_data_by_type();
+        for mut candidate in find_palettes_in_data(&data, min_confidence) {
+            candidate.sector_index = sector.sector_index();
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+/// Scans a block of data for palettes.
+///
+/// Three layouts are detected:
+/// - CLUT commands, an optional `0xC30000??` bank select followed by `0x80`-`0xBF` colour loads
+/// - indexed entries of a 1 byte index and 3 colour bytes, with increasing indexes
+/// - 384 or 768 byte unindexed RGB blocks within the first 64 bytes, scored on how many
+///   distinct colours they hold and how smoothly neighbouring colours change
+///
+/// Candidates do not overlap, and `sector_index` is left at 0.
+pub fn find_palettes_in_data(data: &[u8], min_confidence: f32) -> Vec<PaletteCandidate> {
+    let mut candidates = find_entry_palettes(data);
+    let mut unindexed = Vec::new();
+    for block_size in UNINDEXED_BLOCK_SIZES {
+        for offset in (0..=MAX_UNINDEXED_OFFSET).step_by(4) {
+            if offset + block_size > data.len() {
+                break;
+            }
+            let block = &data[offset..offset + block_size];
+            unindexed.push(PaletteCandidate {
+                sector_index: 0,
+                offset,
+                length: block_size,
+                palette_type: CdiPaletteType::RGB,
+                confidence: score_unindexed_block(block),
+                colors: read_unindexed_palette(block),
+                start_index: 0,
+                commands: Vec::new(),
+            });
+        }
+    }
+    unindexed.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    for candidate in unindexed {
+        let overlaps = candidates
+            .iter()
+            .any(|c| candidate.offset < c.offset + c.length && c.offset < candidate.offset + candidate.length);
+        if !overlaps {
+            candidates.push(candidate);
+        }
+    }
+
+    candidates.retain(|c| c.confidence >= min_confidence);
+    candidates.sort_by_key(|c| c.offset);
+    candidates
+}
+
+/// Finds runs of CLUT commands and indexed palette entries.
+fn find_entry_palettes(data: &[u8]) -> Vec<PaletteCandidate> {
+    let mut candidates = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= data.len() {
+        let has_bank_select = data[offset] == 0xC3 && data[offset + 1] == 0 && data[offset + 2] == 0 && data[offset + 3] < 4;
+        let entries_start = if has_bank_select { offset + 4 } else { offset };
+        let entry_count = count_increasing_entries(data, entries_start);
+
+        if entry_count >= MIN_ENTRY_RUN {
+            let first_index = data[entries_start];
+            let is_clut = (0x80..=0xBF).contains(&first_index) && (has_bank_select || first_index == 0x80);
+            let end = entries_start + entry_count * 4;
+            let (palette_type, colors, start_index, confidence) = if is_clut {
+                let mut clut = ClutState::new();
+                clut.apply_commands(&data[offset..end]);
+                let colors = (0..=255u8).filter(|i| clut.is_loaded(*i)).map(|i| clut.color(i)).collect();
+                let start_index = clut.bank() as usize * ClutState::BANK_SIZE + (first_index - 0x80) as usize;
+                let confidence = if has_bank_select { 1.0 } else { 0.9 };
+                (CdiPaletteType::Clut, colors, start_index, confidence)
+            } else {
+                let confidence = if first_index == 0 { 0.9 } else { 0.7 };
+                let colors = read_indexed_palette(&data[entries_start..end]);
+                (CdiPaletteType::Indexed, colors, first_index as usize, confidence)
+            };
+            candidates.push(PaletteCandidate {
+                sector_index: 0,
+                offset,
+                length: end - offset,
+                palette_type,
+                confidence: confidence * (0.5 + 0.5 * (entry_count as f32 / 32.0).min(1.0)),
+                colors,
+                start_index,
+                commands: if is_clut { data[offset..end].to_vec() } else { Vec::new() },
+            });
+            offset = end;
+        } else {
+            offset += if has_bank_select { 4 } else { 1 };
+        }
+    }
+    candidates
+}
+
+/// Counts the 4 byte entries from `start` whose first bytes are consecutive indexes.
+///
+/// Runs starting at a CLUT colour load stop at `0xBF`, as the bytes after it are other commands.
+fn count_increasing_entries(data: &[u8], start: usize) -> usize {
+    let is_clut_run = data.get(start).is_some_and(|index| (0x80..=0xBF).contains(index));
+    let mut count = 0;
+    while start + (count + 1) * 4 <= data.len() {
+        let index = data[start + count * 4];
+        if count > 0 && index != data[start].wrapping_add(count as u8) {
+            break;
+        }
+        if count > 0 && index == data[start] {
+            break;
+        }
+        if is_clut_run && index > 0xBF {
+            break;
+        }
+        count += 1;
+    }
+    count
+}
+
+/// Scores how much a block of bytes looks like a list of RGB colours, from 0 to 1.
+fn score_unindexed_block(block: &[u8]) -> f32 {
+    let colors: Vec<&[u8]> = block.chunks_exact(3).collect();
+    let distinct: HashSet<&[u8]> = colors.iter().copied().collect();
+    let distinct_ratio = distinct.len() as f32 / colors.len() as f32;
+    if distinct.len() < 8 {
+        return 0.0;
+    }
+
+    let total_difference: u32 = colors
+        .windows(2)
+        .map(|pair| (0..3).map(|c| pair[0][c].abs_diff(pair[1][c]) as u32).sum::<u32>())
+        .sum();
+    let mean_difference = total_difference as f32 / ((colors.len() - 1) * 3) as f32;
+    // random bytes differ by 85 on average, palettes tend to change gradually
+    let smoothness = (1.0 - mean_difference / 85.0).clamp(0.0, 1.0);
+
+    distinct_ratio * smoothness
+}
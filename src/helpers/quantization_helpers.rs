@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use image::{ImageBuffer, Rgba};
+
+pub struct QuantizeConfig {
+    pub image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    pub color_count: usize,
+    pub use_dithering: bool,
+    pub use_transparency: bool,
+    pub transparency_index: u8,
+    pub use_lower_indexes: bool,
+    pub alpha_threshold: u8,
+}
+
+pub struct QuantizedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub palette: Vec<Rgba<u8>>,
+}
+
+/// Reduces an RGBA image to an indexed image with a palette of `color_count` colours.
+///
+/// The palette is built with median cut over the opaque pixels, and pixels are mapped to
+/// their nearest palette entry, optionally with Floyd-Steinberg dithering. Typical counts
+/// are 16 for CLUT4, 128 for CLUT7 and RL7, and 256 for CLUT8.
+///
+/// When `use_transparency` is set, pixels with an alpha below `alpha_threshold` are mapped
+/// to `transparency_index`, and opaque colours are only placed at indexes that a `Clut7Config`
+/// with the same `transparency_index` and `use_lower_indexes` would show as opaque.
+/// The reserved entries are written to the palette as fully transparent.
+pub fn quantize_image(config: QuantizeConfig) -> QuantizedImage {
+    let (width, height) = config.image.dimensions();
+    let color_count = config.color_count.clamp(1, 256);
+    let is_transparent = |pixel: &Rgba<u8>| config.use_transparency && pixel[3] < config.alpha_threshold;
+
+    let opaque_indexes: Vec<u8> = (0..color_count)
+        .filter(|index| !config.use_transparency || !is_reserved_index(&config, *index))
+        .map(|index| index as u8)
+        .collect();
+
+    let mut histogram: HashMap<[u8; 3], u32> = HashMap::new();
+    for pixel in config.image.pixels().filter(|p| !is_transparent(p)) {
+        *histogram.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+    }
+    let colors = median_cut(histogram.into_iter().collect(), opaque_indexes.len());
+
+    let mut palette = vec![Rgba([0, 0, 0, 255]); color_count];
+    if config.use_transparency {
+        for (index, entry) in palette.iter_mut().enumerate() {
+            if is_reserved_index(&config, index) {
+                *entry = Rgba([0, 0, 0, 0]);
+            }
+        }
+    }
+    for (color, index) in colors.iter().zip(&opaque_indexes) {
+        palette[*index as usize] = Rgba([color[0], color[1], color[2], 255]);
+    }
+    let candidates: Vec<(u8, [u8; 3])> = opaque_indexes.iter().zip(&colors).map(|(i, c)| (*i, *c)).collect();
+
+    let mut pixels = vec![config.transparency_index; (width * height) as usize];
+    let mut errors = vec![[0f32; 3]; if config.use_dithering { (width * height) as usize } else { 0 }];
+    let mut nearest_cache: HashMap<[u8; 3], (u8, [u8; 3])> = HashMap::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = config.image.get_pixel(x, y);
+            if is_transparent(pixel) || candidates.is_empty() {
+                continue;
+            }
+            let offset = (y * width + x) as usize;
+            let mut target = [pixel[0], pixel[1], pixel[2]];
+            if config.use_dithering {
+                for channel in 0..3 {
+                    target[channel] = (target[channel] as f32 + errors[offset][channel]).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+
+            let (index, color) = *nearest_cache
+                .entry(target)
+                .or_insert_with(|| nearest_color(&candidates, target));
+            pixels[offset] = index;
+
+            if config.use_dithering {
+                let error = [
+                    target[0] as f32 - color[0] as f32,
+                    target[1] as f32 - color[1] as f32,
+                    target[2] as f32 - color[2] as f32,
+                ];
+                for (dx, dy, weight) in [(1i64, 0i64, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)] {
+                    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                    if nx < 0 || nx >= width as i64 || ny >= height as i64 {
+                        continue;
+                    }
+                    let neighbour = &mut errors[(ny as u32 * width + nx as u32) as usize];
+                    for channel in 0..3 {
+                        neighbour[channel] += error[channel] * weight / 16.0;
+                    }
+                }
+            }
+        }
+    }
+
+    QuantizedImage {
+        width,
+        height,
+        pixels,
+        palette,
+    }
+}
+
+fn is_reserved_index(config: &QuantizeConfig, index: usize) -> bool {
+    let transparency_index = config.transparency_index as usize;
+    index == 0
+        || index == transparency_index
+        || (config.use_lower_indexes && index <= transparency_index)
+        || (!config.use_lower_indexes && index >= transparency_index)
+}
+
+fn nearest_color(candidates: &[(u8, [u8; 3])], target: [u8; 3]) -> (u8, [u8; 3]) {
+    *candidates
+        .iter()
+        .min_by_key(|(_, color)| {
+            (0..3)
+                .map(|c| (color[c] as i32 - target[c] as i32).pow(2))
+                .sum::<i32>()
+        })
+        .unwrap()
+}
+
+/// Splits the weighted colours into at most `count` boxes and returns the average colour of each.
+fn median_cut(colors: Vec<([u8; 3], u32)>, count: usize) -> Vec<[u8; 3]> {
+    if colors.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![colors];
+    while boxes.len() < count {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(index, b)| {
+                let (channel, range) = widest_channel(b);
+                (index, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range);
+
+        let Some((index, channel, _)) = widest else {
+            break;
+        };
+
+        let mut split_box = boxes.swap_remove(index);
+        split_box.sort_by_key(|(color, _)| color[channel]);
+        let total: u32 = split_box.iter().map(|(_, weight)| weight).sum();
+        let mut accumulated = 0;
+        let mut split_at = 1;
+        for (position, (_, weight)) in split_box.iter().enumerate() {
+            accumulated += weight;
+            if accumulated * 2 >= total {
+                split_at = (position + 1).clamp(1, split_box.len() - 1);
+                break;
+            }
+        }
+        let upper = split_box.split_off(split_at);
+        boxes.push(split_box);
+        boxes.push(upper);
+    }
+
+    let mut averages: Vec<[u8; 3]> = boxes
+        .iter()
+        .map(|b| {
+            let total: u64 = b.iter().map(|(_, weight)| *weight as u64).sum();
+            let mut sums = [0u64; 3];
+            for (color, weight) in b {
+                for channel in 0..3 {
+                    sums[channel] += color[channel] as u64 * *weight as u64;
+                }
+            }
+            [
+                ((sums[0] + total / 2) / total) as u8,
+                ((sums[1] + total / 2) / total) as u8,
+                ((sums[2] + total / 2) / total) as u8,
+            ]
+        })
+        .collect();
+    averages.sort();
+    averages
+}
+
+fn widest_channel(colors: &[([u8; 3], u32)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let min = colors.iter().map(|(c, _)| c[channel]).min().unwrap_or(0);
+            let max = colors.iter().map(|(c, _)| c[channel]).max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
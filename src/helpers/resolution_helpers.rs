@@ -0,0 +1,108 @@
+use image::{imageops::FilterType, ImageBuffer, Rgba};
+
+use crate::data::CdiResolution;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoStandard {
+    Ntsc,
+    Pal,
+}
+
+impl VideoStandard {
+    /// Returns the width of a normal resolution pixel relative to its height.
+    ///
+    /// Normal resolution pixels are clocked at 7.5 MHz, against the square pixel rates
+    /// of 6.1364 MHz for NTSC and 7.375 MHz for PAL.
+    pub fn pixel_aspect_ratio(&self) -> f32 {
+        match self {
+            VideoStandard::Ntsc => 6.1364 / 7.5,
+            VideoStandard::Pal => 7.375 / 7.5,
+        }
+    }
+
+    /// Returns the pixel aspect ratio of a resolution as a fraction, for containers that need one.
+    pub fn pixel_aspect_fraction(&self, resolution: CdiResolution) -> (u32, u32) {
+        let (width, height) = match self {
+            VideoStandard::Ntsc => (9, 11),
+            VideoStandard::Pal => (59, 60),
+        };
+        (width * vertical_factor(resolution), height * horizontal_factor(resolution))
+    }
+
+    /// Returns the frame rate as a fraction.
+    pub fn frame_rate(&self) -> (u32, u32) {
+        match self {
+            VideoStandard::Ntsc => (30000, 1001),
+            VideoStandard::Pal => (25, 1),
+        }
+    }
+
+    /// Returns the number of lines in a field.
+    pub fn field_height(&self) -> u32 {
+        match self {
+            VideoStandard::Ntsc => 240,
+            VideoStandard::Pal => 280,
+        }
+    }
+}
+
+/// Returns how many pixels of a resolution cover one normal resolution pixel horizontally.
+fn horizontal_factor(resolution: CdiResolution) -> u32 {
+    match resolution {
+        CdiResolution::Double | CdiResolution::High => 2,
+        _ => 1,
+    }
+}
+
+/// Returns how many lines of a resolution cover one normal resolution line.
+fn vertical_factor(resolution: CdiResolution) -> u32 {
+    match resolution {
+        CdiResolution::High => 2,
+        _ => 1,
+    }
+}
+
+/// Repeats every pixel `x_factor` times horizontally and every line `y_factor` times vertically.
+pub fn repeat_pixels(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, x_factor: u32, y_factor: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let x_factor = x_factor.max(1);
+    let y_factor = y_factor.max(1);
+    ImageBuffer::from_fn(image.width() * x_factor, image.height() * y_factor, |x, y| {
+        *image.get_pixel(x / x_factor, y / y_factor)
+    })
+}
+
+/// Converts an image decoded at one resolution to the pixel grid of another.
+///
+/// Moving to a higher resolution repeats pixels, so a normal resolution plane lines up with
+/// a double resolution one, and moving to a lower resolution drops them.
+pub fn scale_to_resolution(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, from: CdiResolution, to: CdiResolution) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (from_x, from_y) = (horizontal_factor(from), vertical_factor(from));
+    let (to_x, to_y) = (horizontal_factor(to), vertical_factor(to));
+    if to_x >= from_x && to_y >= from_y {
+        return repeat_pixels(image, to_x / from_x, to_y / from_y);
+    }
+    let width = image.width() * to_x / from_x;
+    let height = image.height() * to_y / from_y;
+    image::imageops::resize(image, width, height, FilterType::Nearest)
+}
+
+/// Rescales an image so it has the proportions it would have on a display of the given standard.
+///
+/// Decoded images have square pixels, while CD-i pixels are narrower than they are tall.
+/// The image is stretched rather than shrunk, so no detail is lost: for NTSC, where pixels
+/// are narrow, the height is increased, and for double resolution images, whose pixels are
+/// half as wide again, the height is increased further.
+pub fn correct_aspect_ratio(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    resolution: CdiResolution,
+    standard: VideoStandard,
+    filter: FilterType,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let pixel_aspect_ratio = standard.pixel_aspect_ratio() * vertical_factor(resolution) as f32 / horizontal_factor(resolution) as f32;
+    let (width, height) = if pixel_aspect_ratio < 1.0 {
+        (image.width(), (image.height() as f32 / pixel_aspect_ratio).round() as u32)
+    } else {
+        ((image.width() as f32 * pixel_aspect_ratio).round() as u32, image.height())
+    };
+    image::imageops::resize(image, width, height, filter)
+}
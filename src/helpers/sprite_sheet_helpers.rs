@@ -0,0 +1,143 @@
+use std::fs;
+
+use image::{ImageBuffer, Rgba};
+
+use crate::data::cdi_file::CdiFile;
+use crate::data::cdi_sector::CdiSector;
+use crate::helpers::decode_helpers::{decode_video_record, DecodeContext};
+use crate::helpers::image_format_helpers::DecodeError;
+
+pub struct SpriteSheetConfig {
+    pub channel: u8,
+    /// The palette and dimensions used to decode every record. A width of 0 is guessed,
+    /// which only suits full screen records, so sprites need their width set.
+    pub context: DecodeContext,
+    /// Sprites are placed left to right and wrap to a new row past this width.
+    pub max_width: u32,
+    /// The transparent space left around each sprite.
+    pub padding: u32,
+}
+
+/// Where a decoded record was placed on a sprite sheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteEntry {
+    /// The position of the record among the video records of its channel.
+    pub record_index: usize,
+    pub first_sector: u32,
+    pub last_sector: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct SpriteSheet {
+    pub image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    pub entries: Vec<SpriteEntry>,
+    /// The records that could not be decoded, by record index.
+    pub skipped: Vec<(usize, DecodeError)>,
+}
+
+/// Decodes every video record of a channel and packs the images onto one sheet.
+///
+/// Records are placed in the order they appear, in rows no wider than `max_width` unless a
+/// single sprite is wider, each row being as tall as its tallest sprite.
+pub fn build_sprite_sheet(file: &CdiFile, config: &SpriteSheetConfig) -> SpriteSheet {
+    let records: Vec<Vec<&CdiSector>> = file
+        .get_video_records()
+        .into_iter()
+        .filter(|r| r[0].channel_number() == config.channel)
+        .collect();
+
+    let mut sprites = Vec::new();
+    let mut skipped = Vec::new();
+    for (record_index, record) in records.iter().enumerate() {
+        match decode_video_record(record, &config.context) {
+            Ok(image) => sprites.push((record_index, record, image)),
+            Err(error) => skipped.push((record_index, error)),
+        }
+    }
+
+    let mut entries = Vec::new();
+    let (mut x, mut y, mut row_height) = (config.padding, config.padding, 0);
+    let (mut sheet_width, mut sheet_height) = (0, 0);
+    for (record_index, record, image) in &sprites {
+        let (width, height) = image.dimensions();
+        if x > config.padding && x + width + config.padding > config.max_width {
+            x = config.padding;
+            y += row_height + config.padding;
+            row_height = 0;
+        }
+        entries.push(SpriteEntry {
+            record_index: *record_index,
+            first_sector: record[0].sector_index(),
+            last_sector: record[record.len() - 1].sector_index(),
+            x,
+            y,
+            width,
+            height,
+        });
+        x += width + config.padding;
+        row_height = row_height.max(height);
+        sheet_width = sheet_width.max(x);
+        sheet_height = sheet_height.max(y + height + config.padding);
+    }
+
+    let mut image = ImageBuffer::new(sheet_width, sheet_height);
+    for ((_, _, sprite), entry) in sprites.iter().zip(&entries) {
+        image::imageops::replace(&mut image, sprite, entry.x as i64, entry.y as i64);
+    }
+    SpriteSheet { image, entries, skipped }
+}
+
+impl SpriteSheet {
+    pub fn atlas_json(&self) -> String {
+        let sprites: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                format!(
+                    "    {{ \"record\": {}, \"first_sector\": {}, \"last_sector\": {}, \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {} }}",
+                    e.record_index, e.first_sector, e.last_sector, e.x, e.y, e.width, e.height
+                )
+            })
+            .collect();
+        let sprites = if sprites.is_empty() {
+            "[]".to_string()
+        } else {
+            format!("[\n{}\n  ]", sprites.join(",\n"))
+        };
+        format!(
+            "{{\n  \"width\": {},\n  \"height\": {},\n  \"sprites\": {}\n}}\n",
+            self.image.width(),
+            self.image.height(),
+            sprites
+        )
+    }
+
+    pub fn atlas_toml(&self) -> String {
+        let mut toml = format!("width = {}\nheight = {}\n", self.image.width(), self.image.height());
+        for e in &self.entries {
+            toml.push_str(&format!(
+                "\n[[sprites]]\nrecord = {}\nfirst_sector = {}\nlast_sector = {}\nx = {}\ny = {}\nwidth = {}\nheight = {}\n",
+                e.record_index, e.first_sector, e.last_sector, e.x, e.y, e.width, e.height
+            ));
+        }
+        toml
+    }
+
+    /// Saves the sheet as a PNG and its atlas as TOML when `atlas_path` ends in `.toml`, or JSON otherwise.
+    pub fn save(&self, png_path: &str, atlas_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.entries.is_empty() {
+            return Err("the sprite sheet is empty".into());
+        }
+        self.image.save(png_path)?;
+        let atlas = if atlas_path.to_ascii_lowercase().ends_with(".toml") {
+            self.atlas_toml()
+        } else {
+            self.atlas_json()
+        };
+        fs::write(atlas_path, atlas)?;
+        Ok(())
+    }
+}
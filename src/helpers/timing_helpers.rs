@@ -0,0 +1,117 @@
+use std::fs;
+
+use image::{ImageBuffer, Rgba};
+
+use crate::data::cdi_sector::CdiSector;
+use crate::helpers::animation_helpers::AnimationFrame;
+
+/// The number of sectors a single speed player reads each second.
+pub const SECTORS_PER_SECOND: u32 = 75;
+
+/// When a frame is displayed, relative to the first frame of a sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameTiming {
+    /// The sector that triggers the display of the frame.
+    pub sector_index: u32,
+    pub start_ms: u32,
+    pub duration_ms: u32,
+}
+
+/// Returns the sector at which a record is displayed.
+///
+/// This is the first sector with the trigger bit set, or the last sector of the record when
+/// none is, as the record can only be shown once all of it has been read.
+pub fn trigger_sector_index(record: &[&CdiSector]) -> Option<u32> {
+    record
+        .iter()
+        .find(|s| s.submode().is_trigger())
+        .or(record.last())
+        .map(|s| s.sector_index())
+}
+
+/// Computes display times for records from the sectors that trigger them.
+///
+/// One timing is returned for each record, so they can be matched by position. Times are
+/// measured from the trigger of the first record at `speed` times the single speed sector
+/// rate. Each frame lasts until the next one is triggered, and the last frame lasts as long
+/// as the one before it, or one second of sectors when there is only one frame. Empty
+/// records, which have no trigger, start with the frame after them and last no time.
+pub fn frame_timings(records: &[Vec<&CdiSector>], speed: u32) -> Vec<FrameTiming> {
+    let sector_rate = SECTORS_PER_SECOND * speed.max(1);
+    let trigger_indexes: Vec<u32> = records.iter().filter_map(|r| trigger_sector_index(r)).collect();
+    let Some(first_index) = trigger_indexes.first().copied() else {
+        return records.iter().map(|_| FrameTiming { sector_index: 0, start_ms: 0, duration_ms: 0 }).collect();
+    };
+    let to_ms = |index: u32| (index.saturating_sub(first_index) as u64 * 1000 / sector_rate as u64) as u32;
+
+    let mut timings: Vec<FrameTiming> = trigger_indexes
+        .windows(2)
+        .map(|pair| FrameTiming {
+            sector_index: pair[0],
+            start_ms: to_ms(pair[0]),
+            duration_ms: to_ms(pair[1]).saturating_sub(to_ms(pair[0])),
+        })
+        .collect();
+
+    let last_index = trigger_indexes[trigger_indexes.len() - 1];
+    let last_duration = timings.last().map(|t| t.duration_ms).unwrap_or(1000);
+    timings.push(FrameTiming {
+        sector_index: last_index,
+        start_ms: to_ms(last_index),
+        duration_ms: last_duration,
+    });
+
+    // Give empty records a place without a duration, at the next frame or after the last one
+    let mut known_timings = timings.into_iter().peekable();
+    let mut end_ms = 0;
+    records
+        .iter()
+        .map(|r| {
+            let timing = match (trigger_sector_index(r), known_timings.peek()) {
+                (Some(_), _) => known_timings.next().unwrap(),
+                (None, Some(next)) => FrameTiming { duration_ms: 0, ..*next },
+                (None, None) => FrameTiming { sector_index: last_index, start_ms: end_ms, duration_ms: 0 },
+            };
+            end_ms = timing.start_ms + timing.duration_ms;
+            timing
+        })
+        .collect()
+}
+
+/// Pairs decoded frames with their timings for `write_animation`.
+///
+/// `timings` must come from the records the frames were decoded from, in the same order, as
+/// `frame_timings` returns one timing per record. Frames without a timing are dropped.
+pub fn timed_frames(images: Vec<ImageBuffer<Rgba<u8>, Vec<u8>>>, timings: &[FrameTiming]) -> Vec<AnimationFrame> {
+    images
+        .into_iter()
+        .zip(timings)
+        .map(|(image, timing)| AnimationFrame {
+            image,
+            delay_ms: timing.duration_ms,
+        })
+        .collect()
+}
+
+/// Formats timings as a JSON timeline, one object per frame.
+pub fn timeline_json(timings: &[FrameTiming]) -> String {
+    let frames: Vec<String> = timings
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            format!(
+                "    {{ \"frame\": {}, \"sector\": {}, \"start_ms\": {}, \"duration_ms\": {} }}",
+                i, t.sector_index, t.start_ms, t.duration_ms
+            )
+        })
+        .collect();
+    if frames.is_empty() {
+        return "{\n  \"frames\": []\n}\n".to_string();
+    }
+    format!("{{\n  \"frames\": [\n{}\n  ]\n}}\n", frames.join(",\n"))
+}
+
+pub fn write_timeline_json(path: &str, timings: &[FrameTiming]) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(path, timeline_json(timings))?;
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::{fs::File, io::BufWriter, io::Write};
+
+use image::{imageops::FilterType, Rgba};
+
+use crate::data::cdi_file::CdiFile;
+use crate::data::cdi_sector::CdiSector;
+use crate::data::{CdiResolution, CdiVideoType};
+use crate::helpers::animation_helpers::AnimationFrame;
+use crate::helpers::audio_helpers::{decode_audio_sectors, offset_audio, write_wav};
+use crate::helpers::decode_helpers::{decode_video_record, DecodeContext};
+use crate::helpers::resolution_helpers::VideoStandard;
+use crate::helpers::timing_helpers::{frame_timings, timed_frames, trigger_sector_index, SECTORS_PER_SECOND};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Y4mConfig {
+    pub frame_rate: (u32, u32),
+    pub pixel_aspect: (u32, u32),
+}
+
+/// Converts a pixel to limited range BT.601 YCbCr.
+fn rgb_to_ycbcr(pixel: &Rgba<u8>) -> [u8; 3] {
+    let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+    let y = 16 + (66 * r + 129 * g + 25 * b + 128) / 256;
+    let cb = 128 + (-38 * r - 74 * g + 112 * b + 128) / 256;
+    let cr = 128 + (112 * r - 94 * g - 18 * b + 128) / 256;
+    [y as u8, cb as u8, cr as u8]
+}
+
+/// Writes frames as an uncompressed 4:4:4 YUV4MPEG2 stream at a constant frame rate.
+///
+/// Frames are repeated to cover their delay at `config.frame_rate`, so a sequence with irregular
+/// timing keeps its pace. Every frame is resized to the dimensions of the first, and alpha
+/// is ignored.
+pub fn write_y4m(path: &str, frames: &[AnimationFrame], config: Y4mConfig) -> Result<usize, Box<dyn std::error::Error>> {
+    let Some(first_frame) = frames.first() else {
+        return Err("a video needs at least one frame".into());
+    };
+    let (width, height) = first_frame.image.dimensions();
+    let (rate_numerator, rate_denominator) = config.frame_rate;
+    if rate_numerator == 0 || rate_denominator == 0 {
+        return Err("invalid frame rate".into());
+    }
+    let frame_number = |ms: u64| (ms * rate_numerator as u64 + rate_denominator as u64 * 500) / (rate_denominator as u64 * 1000);
+
+    let mut output = BufWriter::new(File::create(path)?);
+    writeln!(
+        output,
+        "YUV4MPEG2 W{} H{} F{}:{} Ip A{}:{} C444 XCOLORRANGE=LIMITED",
+        width, height, rate_numerator, rate_denominator, config.pixel_aspect.0, config.pixel_aspect.1
+    )?;
+
+    let mut written = 0;
+    let mut start_ms = 0;
+    for frame in frames {
+        let end_ms = start_ms + frame.delay_ms as u64;
+        let repeats = frame_number(end_ms) - frame_number(start_ms);
+        start_ms = end_ms;
+        if repeats == 0 {
+            continue;
+        }
+
+        let image = if frame.image.dimensions() == (width, height) {
+            frame.image.clone()
+        } else {
+            image::imageops::resize(&frame.image, width, height, FilterType::Nearest)
+        };
+        let ycbcr: Vec<[u8; 3]> = image.pixels().map(rgb_to_ycbcr).collect();
+        let mut planes = Vec::with_capacity(ycbcr.len() * 3);
+        for component in 0..3 {
+            planes.extend(ycbcr.iter().map(|p| p[component]));
+        }
+        for _ in 0..repeats {
+            output.write_all(b"FRAME\n")?;
+            output.write_all(&planes)?;
+            written += 1;
+        }
+    }
+    output.flush()?;
+    Ok(written)
+}
+
+pub struct AvExportConfig {
+    pub channel: u8,
+    pub context: DecodeContext,
+    pub standard: VideoStandard,
+    /// The disc speed, 1 for a single speed player.
+    pub speed: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AvExportSummary {
+    pub decoded_records: usize,
+    pub skipped_records: usize,
+    pub video_frames: usize,
+    pub audio_samples: usize,
+    /// When the audio starts relative to the first video frame.
+    pub audio_offset_ms: i64,
+}
+
+/// Decodes the video and audio of a real-time file channel into a YUV4MPEG2 video and a WAV file.
+///
+/// Video records are timed from their trigger sectors with `frame_timings` and records that
+/// cannot be decoded are skipped. The pixel aspect ratio follows the display standard and the
+/// resolution of the first record, or is square when the context already corrects it. The
+/// audio sectors of the same channel are decoded and offset so both files start at the first
+/// video frame. Without a `wav_path`, audio is left out.
+pub fn export_channel_av(
+    file: &CdiFile,
+    config: &AvExportConfig,
+    y4m_path: &str,
+    wav_path: Option<&str>,
+) -> Result<AvExportSummary, Box<dyn std::error::Error>> {
+    let records: Vec<Vec<&CdiSector>> = file
+        .get_video_records()
+        .into_iter()
+        .filter(|r| r[0].channel_number() == config.channel && r.iter().any(|s| s.coding_info().coding() <= CdiVideoType::QHY as u8))
+        .collect();
+
+    let mut images = Vec::new();
+    let mut decoded = Vec::new();
+    for record in &records {
+        if let Ok(image) = decode_video_record(record, &config.context) {
+            images.push(image);
+            decoded.push(record.clone());
+        }
+    }
+    if decoded.is_empty() {
+        return Err(format!("no decodable video records on channel {}", config.channel).into());
+    }
+
+    let resolution = match config.context.display_resolution {
+        Some(resolution) => resolution,
+        None if decoded[0].iter().any(|s| s.coding_info().coding() == CdiVideoType::QHY as u8) => CdiResolution::Double,
+        None => decoded[0][0].coding_info().resolution_type(),
+    };
+    let pixel_aspect = match config.context.aspect_correction {
+        Some(_) => (1, 1),
+        None => config.standard.pixel_aspect_fraction(resolution),
+    };
+
+    let timings = frame_timings(&decoded, config.speed);
+    let video_frames = write_y4m(
+        y4m_path,
+        &timed_frames(images, &timings),
+        Y4mConfig {
+            frame_rate: config.standard.frame_rate(),
+            pixel_aspect,
+        },
+    )?;
+
+    let mut summary = AvExportSummary {
+        decoded_records: decoded.len(),
+        skipped_records: records.len() - decoded.len(),
+        video_frames,
+        audio_samples: 0,
+        audio_offset_ms: 0,
+    };
+
+    if let Some(wav_path) = wav_path {
+        let audio_sectors: Vec<&CdiSector> = file
+            .get_audio_sectors()
+            .into_iter()
+            .filter(|s| s.channel_number() == config.channel)
+            .collect();
+        if let (Some(audio_start), Some(video_start)) = (audio_sectors.first(), trigger_sector_index(&decoded[0])) {
+            let sector_rate = (SECTORS_PER_SECOND * config.speed.max(1)) as i64;
+            summary.audio_offset_ms = (audio_start.sector_index() as i64 - video_start as i64) * 1000 / sector_rate;
+        }
+        let audio = offset_audio(&decode_audio_sectors(&audio_sectors), summary.audio_offset_ms);
+        write_wav(wav_path, &audio)?;
+        summary.audio_samples = audio.samples.len();
+    }
+
+    Ok(summary)
+}
@@ -8,6 +8,9 @@
 //! 
 //! The `CdiSector` type represents a single sector of a CD-i file and provides methods
 //!  for accessing the sector's data, coding and subheader information.
+//!
+//! The `CdiDisc` type reads the file structure of a CD-i disc image, and the `cdi`
+//! binary exposes the helpers from the command line.
 
 pub mod data;
 pub mod helpers;
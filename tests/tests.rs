@@ -1,13 +1,39 @@
 
-use image::{ImageBuffer, Rgba};
+use image::{imageops::FilterType, ImageBuffer, Rgba};
 use std::fs::File;
 use std::io::prelude::*;
 
+use og_lib_cdi::data::cdi_disc::CdiDisc;
 use og_lib_cdi::data::cdi_file::CdiFile;
 
 use og_lib_cdi::data::cdi_sector::CdiSector;
-use og_lib_cdi::helpers::color_helpers::{read_clut_banks, read_unindexed_palette, write_palette};
-use og_lib_cdi::helpers::image_format_helpers::{create_gif, decode_clut7_image, decode_dyuv_image, decode_rle_image, Clut7Config, DyuvImageConfig, RleImageConfig};
+use og_lib_cdi::data::cdi_coding_info::CdiCodingInfo;
+use og_lib_cdi::data::{CdiPaletteType, CdiResolution};
+use og_lib_cdi::helpers::animation_helpers::{write_animation, AnimationConfig, AnimationFormat, AnimationFrame};
+use og_lib_cdi::helpers::audio_helpers::{decode_audio_sectors, offset_audio, write_wav};
+use og_lib_cdi::helpers::color_helpers::{
+    read_act_palette, read_clut_banks, read_gpl_palette, read_hex_palette, read_jasc_palette, read_unindexed_palette,
+    write_act_palette, write_gpl_palette, write_hex_palette, write_jasc_palette, write_palette, ClutState,
+};
+use og_lib_cdi::helpers::compositing_helpers::{
+    backdrop_color, composite_planes, CompositeConfig, Matte, PlaneOrder, PlaneSettings, TransparencyMode,
+};
+use og_lib_cdi::helpers::display_control_helpers::{
+    parse_display_instructions, render_clut_lines, render_dyuv_lines, render_rl7_lines, simulate_display_program,
+    DisplayControlProgram, DisplayInstruction, DisplayParameters,
+};
+use og_lib_cdi::helpers::decode_helpers::{decode_video_record, DecodeContext};
+use og_lib_cdi::helpers::extraction_helpers::{extract_all_images, manifest_json, ExtractOptions};
+use og_lib_cdi::helpers::image_analysis_helpers::guess_image_dimensions;
+use og_lib_cdi::helpers::interlace_helpers::{decode_field_pair, pair_field_records, weave_fields};
+use og_lib_cdi::helpers::mpeg_helpers::{demux_mpeg, write_mpeg_streams, MpegGap};
+use og_lib_cdi::helpers::palette_discovery_helpers::{find_palette_candidates, find_palettes_in_data};
+use og_lib_cdi::helpers::quantization_helpers::{quantize_image, QuantizeConfig};
+use og_lib_cdi::helpers::resolution_helpers::{correct_aspect_ratio, scale_to_resolution, VideoStandard};
+use og_lib_cdi::helpers::sprite_sheet_helpers::{build_sprite_sheet, SpriteSheetConfig};
+use og_lib_cdi::helpers::timing_helpers::{frame_timings, timed_frames, timeline_json};
+use og_lib_cdi::helpers::video_export_helpers::{export_channel_av, write_y4m, AvExportConfig, Y4mConfig};
+use og_lib_cdi::helpers::image_format_helpers::{create_gif, decode_rle_bytes, decode_clut4_image, decode_clut7_image, decode_clut8_image, decode_dyuv_image, decode_dyuv_image_with_line_starts, decode_dyuv_luminance, decode_qhy_image, decode_rl3_bytes, decode_rl3_image, decode_rl7_lines, decode_rle_image, decode_rgb555_image, encode_dyuv_image, encode_rl3_bytes, encode_rl7_bytes, Clut4Config, Clut7Config, Clut8Config, DecodeError, DyuvEncoderConfig, DyuvImageConfig, QhyImageConfig, Rgb555Config, RleImageConfig, RleLineStatus};
 // test creating a cdifile
 
 #[test]
@@ -73,7 +99,7 @@ fn test_dyuv_image() {
         initial_u: 128,
         initial_v: 128,
     };
-    let image = decode_dyuv_image(dyuv_image);
+    let image = decode_dyuv_image(dyuv_image).unwrap();
     assert_ne!(image.len(), 0);
     image.save("C:/Dev/Projects/Gaming/CD-i/FILES/dyuv_test.png").unwrap();
 }
@@ -108,7 +134,7 @@ fn test_clut7_image() {
         use_lower_indexes: true,
     };
 
-    let image = decode_clut7_image(clut_image);
+    let image = decode_clut7_image(clut_image).unwrap();
     assert_ne!(image.len(), 0);
     image.save("C:/Dev/Projects/Gaming/CD-i/FILES/clut7_test.png").unwrap();
 }
@@ -141,7 +167,7 @@ fn test_rle_image() {
         height: 280,
     };
 
-    let image = decode_rle_image(rle_image);
+    let image = decode_rle_image(rle_image).unwrap();
     assert_ne!(image.len(), 0);
     image.save("C:/Dev/Projects/Gaming/CD-i/FILES/rle_test.png").unwrap();
 }
@@ -189,7 +215,7 @@ fn test_gif_output() {
                 use_transparency: false,
                 height: 240,
             };
-            if let image = decode_rle_image(rle_image) {
+            if let Ok(image) = decode_rle_image(rle_image) {
                 images.push(image);
             }
             byte_groups.clear();
@@ -199,3 +225,1636 @@ fn test_gif_output() {
     create_gif(images, "C:/Dev/Projects/Gaming/CD-i/FILES/plunderball_intro.gif",384,280).unwrap();
     
 }
+
+fn max_channel_difference(a: &ImageBuffer<Rgba<u8>, Vec<u8>>, b: &ImageBuffer<Rgba<u8>, Vec<u8>>, skip_columns: u32) -> u8 {
+    let mut max_difference = 0;
+    for (x, y, pixel) in a.enumerate_pixels() {
+        if x < skip_columns {
+            continue;
+        }
+        let other = b.get_pixel(x, y);
+        for channel in 0..3 {
+            max_difference = max_difference.max(pixel[channel].abs_diff(other[channel]));
+        }
+    }
+    max_difference
+}
+
+#[test]
+fn test_dyuv_encode_round_trip() {
+    let source = ImageBuffer::from_fn(64, 8, |x, y| {
+        Rgba([(x * 2 + 40) as u8, (y * 8 + 60) as u8, 120, 255])
+    });
+    let encoded = encode_dyuv_image(DyuvEncoderConfig {
+        image: source.clone(),
+        initial_y: 16,
+        initial_u: 128,
+        initial_v: 128,
+        use_line_start_values: false,
+    });
+    assert_eq!(encoded.encoded_data.len(), 64 * 8);
+    assert_eq!(encoded.line_start_values.len(), 8);
+
+    let decoded = decode_dyuv_image(DyuvImageConfig {
+        width: 64,
+        height: 8,
+        encoded_data: encoded.encoded_data,
+        initial_y: 16,
+        initial_u: 128,
+        initial_v: 128,
+    }).unwrap();
+    // the first pixels converge from the fixed start values, the rest must not drift
+    assert!(max_channel_difference(&source, &decoded, 8) <= 12);
+}
+
+#[test]
+fn test_dyuv_luminance() {
+    // A blue image has little green but a clear luminance, which is what dimensions are scored on
+    let source = ImageBuffer::from_fn(16, 2, |x, _| Rgba([0, 0, if x < 8 { 60 } else { 255 }, 255]));
+    let encoded = encode_dyuv_image(DyuvEncoderConfig {
+        image: source,
+        initial_y: 16,
+        initial_u: 128,
+        initial_v: 128,
+        use_line_start_values: true,
+    });
+    let config = |encoded_data: Vec<u8>| DyuvImageConfig {
+        width: 16,
+        height: 2,
+        encoded_data,
+        initial_y: encoded.line_start_values[0].y as u32,
+        initial_u: encoded.line_start_values[0].u as u32,
+        initial_v: encoded.line_start_values[0].v as u32,
+    };
+    let luminance = decode_dyuv_luminance(config(encoded.encoded_data.clone())).unwrap();
+    assert_eq!(luminance.len(), 32);
+    assert!(luminance[15] > luminance[0] + 20);
+    let image = decode_dyuv_image(config(encoded.encoded_data.clone())).unwrap();
+    assert!(image.get_pixel(15, 0)[1] < 20);
+    assert!(decode_dyuv_luminance(config(vec![0; 8])).is_err());
+}
+
+#[test]
+fn test_dyuv_encode_line_start_values() {
+    let source = ImageBuffer::from_fn(32, 4, |_, y| Rgba([200, (y * 40) as u8, 30, 255]));
+    let encoded = encode_dyuv_image(DyuvEncoderConfig {
+        image: source.clone(),
+        initial_y: 16,
+        initial_u: 128,
+        initial_v: 128,
+        use_line_start_values: true,
+    });
+    assert_ne!(encoded.line_start_values[0], encoded.line_start_values[3]);
+
+    let decoded = decode_dyuv_image_with_line_starts(
+        DyuvImageConfig {
+            width: encoded.width,
+            height: encoded.height,
+            encoded_data: encoded.encoded_data,
+            initial_y: 16,
+            initial_u: 128,
+            initial_v: 128,
+        },
+        &encoded.line_start_values,
+    ).unwrap();
+    assert!(max_channel_difference(&source, &decoded, 0) <= 12);
+}
+
+#[test]
+fn test_dyuv_encode_chroma_ramp() {
+    // The decoder gives the second pixel of each pair the new chroma and the first pixel the
+    // average with the previous pair, so aiming at the pair average makes a ramp lag behind
+    let source = ImageBuffer::from_fn(64, 1, |x, _| Rgba([100, 100, (x * 4) as u8, 255]));
+    let encoded = encode_dyuv_image(DyuvEncoderConfig {
+        image: source.clone(),
+        initial_y: 16,
+        initial_u: 128,
+        initial_v: 128,
+        use_line_start_values: true,
+    });
+    let decoded = decode_dyuv_image_with_line_starts(
+        DyuvImageConfig {
+            width: 64,
+            height: 1,
+            encoded_data: encoded.encoded_data,
+            initial_y: 16,
+            initial_u: 128,
+            initial_v: 128,
+        },
+        &encoded.line_start_values,
+    ).unwrap();
+    let total_error: i32 = (8..64).map(|x| decoded.get_pixel(x, 0)[2] as i32 - source.get_pixel(x, 0)[2] as i32).sum();
+    assert!(total_error.abs() <= 2 * 56, "blue drifts by {} over the line", total_error);
+}
+
+fn grey_palette(count: usize) -> Vec<Rgba<u8>> {
+    (0..count).map(|i| Rgba([i as u8, i as u8, i as u8, 255])).collect()
+}
+
+#[test]
+fn test_clut4_image() {
+    let encoded_data = vec![0x12, 0x34, 0x56, 0x78];
+    let config = |high_nibble_first| Clut4Config {
+        width: 4,
+        height: 2,
+        encoded_data: encoded_data.clone(),
+        clut_data: grey_palette(16),
+        use_transparency: false,
+        transparency_index: 0,
+        use_lower_indexes: false,
+        high_nibble_first,
+    };
+
+    let image = decode_clut4_image(config(true)).unwrap();
+    assert_eq!(image.get_pixel(0, 0)[0], 1);
+    assert_eq!(image.get_pixel(1, 0)[0], 2);
+    assert_eq!(image.get_pixel(3, 1)[0], 8);
+
+    let image = decode_clut4_image(config(false)).unwrap();
+    assert_eq!(image.get_pixel(0, 0)[0], 2);
+    assert_eq!(image.get_pixel(1, 0)[0], 1);
+    assert_eq!(image.get_pixel(3, 1)[0], 7);
+}
+
+#[test]
+fn test_clut4_double_resolution_transparency() {
+    let image = decode_clut4_image(Clut4Config {
+        width: 768,
+        height: 1,
+        encoded_data: vec![0x0F; 384],
+        clut_data: grey_palette(16),
+        use_transparency: true,
+        transparency_index: 0,
+        use_lower_indexes: true,
+        high_nibble_first: true,
+    }).unwrap();
+    assert_eq!(image.width(), 768);
+    assert_eq!(image.get_pixel(766, 0)[3], 0);
+    assert_eq!(*image.get_pixel(767, 0), Rgba([15, 15, 15, 255]));
+}
+
+#[test]
+fn test_clut8_image() {
+    let image = decode_clut8_image(Clut8Config {
+        width: 16,
+        height: 16,
+        encoded_data: (0..=255).collect(),
+        clut_data: grey_palette(256),
+        use_transparency: true,
+        transparency_index: 200,
+        use_lower_indexes: false,
+    }).unwrap();
+    assert_eq!(*image.get_pixel(15, 11), Rgba([191, 191, 191, 255]));
+    assert_eq!(image.get_pixel(8, 12)[3], 0);
+}
+
+#[test]
+fn test_rl3_bytes() {
+    // a single pair, a run of 2 pairs, then a run to the end of the line
+    let rle_data = [0x12, 0xB4, 0x02, 0xF7, 0x00];
+    let pixels = decode_rl3_bytes(&rle_data, 12);
+    assert_eq!(pixels, vec![1, 2, 3, 4, 3, 4, 7, 7, 7, 7, 7, 7]);
+}
+
+#[test]
+fn test_rl3_image() {
+    let rle_data = [0x81, 0x00, 0x23, 0xC5, 0x00];
+    let image = decode_rl3_image(RleImageConfig {
+        encoded_data: rle_data.to_vec(),
+        line_width: 8,
+        height: 0,
+        clut_data: grey_palette(8),
+        use_transparency: false,
+    }).unwrap();
+    assert_eq!(image.dimensions(), (8, 2));
+    assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+    assert_eq!(*image.get_pixel(1, 0), Rgba([1, 1, 1, 255]));
+    assert_eq!(*image.get_pixel(0, 1), Rgba([2, 2, 2, 255]));
+    assert_eq!(*image.get_pixel(7, 1), Rgba([5, 5, 5, 255]));
+}
+
+const VIDEO_SUBMODE: u8 = 0b0000_0010;
+
+fn build_sector(file_number: u8, channel: u8, submode: u8, coding: u8, payload: &[u8]) -> Vec<u8> {
+    let mut sector = vec![0u8; 2352];
+    sector[1..11].fill(0xFF);
+    let sub_header = [file_number, channel, submode, coding];
+    sector[16..20].copy_from_slice(&sub_header);
+    sector[20..24].copy_from_slice(&sub_header);
+    sector[24..24 + payload.len()].copy_from_slice(payload);
+    sector
+}
+
+fn write_test_file(name: &str, sectors: &[Vec<u8>]) -> CdiFile {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, sectors.concat()).unwrap();
+    CdiFile::new(path.to_string_lossy().to_string())
+}
+
+#[test]
+fn test_rgb555_image() {
+    let image = decode_rgb555_image(Rgb555Config {
+        width: 3,
+        height: 1,
+        lower_data: vec![0x00, 0xE0, 0x1F],
+        upper_data: vec![0xFC, 0x03, 0x00],
+        use_transparency: true,
+        transparent_bit_set: false,
+    }).unwrap();
+    assert_eq!(*image.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+    assert_eq!(*image.get_pixel(1, 0), Rgba([0, 0, 0, 0]));
+    assert_eq!(image.get_pixel(2, 0)[3], 0);
+
+    let image = decode_rgb555_image(Rgb555Config {
+        width: 3,
+        height: 1,
+        lower_data: vec![0x00, 0xE0, 0x1F],
+        upper_data: vec![0xFC, 0x03, 0x00],
+        use_transparency: false,
+        transparent_bit_set: false,
+    }).unwrap();
+    assert_eq!(*image.get_pixel(1, 0), Rgba([0, 255, 0, 255]));
+    assert_eq!(*image.get_pixel(2, 0), Rgba([0, 0, 255, 255]));
+}
+
+#[test]
+fn test_rgb555_sector_pairs() {
+    let file = write_test_file(
+        "og_lib_cdi_rgb555.rtf",
+        &[
+            build_sector(1, 0, VIDEO_SUBMODE, 6, &[1]),
+            build_sector(1, 1, VIDEO_SUBMODE, 6, &[2]),
+            build_sector(1, 1, VIDEO_SUBMODE, 7, &[3]),
+            build_sector(2, 0, VIDEO_SUBMODE, 7, &[4]),
+            build_sector(1, 0, VIDEO_SUBMODE, 7, &[5]),
+            build_sector(1, 2, VIDEO_SUBMODE, 7, &[6]),
+        ],
+    );
+    let pairs: Vec<(u32, u32)> = file
+        .get_rgb555_sector_pairs()
+        .iter()
+        .map(|(lower, upper)| (lower.sector_index(), upper.sector_index()))
+        .collect();
+    // interleaved channels of the same file are not paired with each other
+    assert_eq!(pairs, vec![(1, 2), (0, 4)]);
+}
+
+#[test]
+fn test_qhy_image() {
+    let base = ImageBuffer::from_fn(8, 2, |_, _| Rgba([100, 100, 100, 255]));
+    let encoded = encode_dyuv_image(DyuvEncoderConfig {
+        image: base,
+        initial_y: 16,
+        initial_u: 128,
+        initial_v: 128,
+        use_line_start_values: true,
+    });
+    let start = encoded.line_start_values[0];
+    let dyuv_config = |data: Vec<u8>| DyuvImageConfig {
+        width: 8,
+        height: 2,
+        encoded_data: data,
+        initial_y: start.y as u32,
+        initial_u: start.u as u32,
+        initial_v: start.v as u32,
+    };
+    let base_image = decode_dyuv_image(dyuv_config(encoded.encoded_data.clone())).unwrap();
+
+    // no detail on the left pixels, +4 luminance on the right pixels
+    let image = decode_qhy_image(QhyImageConfig {
+        width: 8,
+        height: 2,
+        dyuv_data: encoded.encoded_data,
+        qhy_data: vec![0x02; 16],
+        initial_y: start.y as u32,
+        initial_u: start.u as u32,
+        initial_v: start.v as u32,
+    }).unwrap();
+    assert_eq!(image.dimensions(), (16, 2));
+    for (x, y, pixel) in base_image.enumerate_pixels() {
+        assert_eq!(image.get_pixel(x * 2, y), pixel);
+        assert_eq!(image.get_pixel(x * 2 + 1, y)[1], pixel[1] + 4);
+    }
+}
+
+#[test]
+fn test_rl7_lines() {
+    // a run of 1, a single pixel, then a run to the end of the line, followed by a short line
+    let rle_data = [0x85, 0x01, 0x06, 0x87, 0x00, 0x01, 0x82, 0x02];
+    let result = decode_rl7_lines(&rle_data, 6, 0);
+    assert_eq!(result.line_count(), 2);
+    assert_eq!(result.clean_line_count(), 1);
+    assert_eq!(result.line_offsets, vec![0, 5]);
+    assert_eq!(result.line(0), &[5, 6, 7, 7, 7, 7]);
+    assert_eq!(result.line(1), &[1, 2, 2, 0, 0, 0]);
+    assert_eq!(result.line_status[1], RleLineStatus::Padded);
+}
+
+#[test]
+fn test_rl7_lines_recovers_from_corruption() {
+    let rle_data = [
+        // line 0: a corrupt run length overflows, the rest of the line is skipped
+        0x81, 0x7F, 0x02, 0x83, 0x00,
+        // line 1 and 2 are intact
+        0x84, 0x00,
+        0x05, 0x85, 0x00,
+    ];
+    let result = decode_rl7_lines(&rle_data, 4, 4);
+    assert_eq!(result.line_count(), 4);
+    assert_eq!(result.line_status[0], RleLineStatus::Truncated);
+    assert_eq!(result.line(0), &[1, 1, 1, 1]);
+    assert_eq!(result.line(1), &[4, 4, 4, 4]);
+    assert_eq!(result.line(2), &[5, 5, 5, 5]);
+    assert_eq!(result.line_status[3], RleLineStatus::Padded);
+    assert_eq!(result.clean_line_count(), 2);
+    assert_eq!(result.pixels.len(), 16);
+}
+
+fn sample_indexed_pixels(line_width: usize, height: usize, color_mask: u8) -> Vec<u8> {
+    (0..line_width * height)
+        .map(|i| {
+            let (x, y) = (i % line_width, i / line_width);
+            match y % 3 {
+                0 => ((x / 7) as u8) & color_mask,
+                1 => ((x * y) as u8 / 3) & color_mask,
+                _ => if x < 300 { 1 } else { 2 },
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_rl7_encode_round_trip() {
+    let pixels = sample_indexed_pixels(384, 6, 0x7F);
+    let encoded = encode_rl7_bytes(&pixels, 384);
+    assert!(encoded.len() < pixels.len());
+    assert_eq!(decode_rle_bytes(&encoded, 384), pixels);
+    assert_eq!(decode_rl7_lines(&encoded, 384, 0).pixels, pixels);
+    // runs of a full line collapse to a single end of line run
+    assert_eq!(encode_rl7_bytes(&[3; 384], 384), vec![0x83, 0x00]);
+}
+
+#[test]
+fn test_rl3_encode_round_trip() {
+    let pixels = sample_indexed_pixels(768, 6, 0x07);
+    let encoded = encode_rl3_bytes(&pixels, 768);
+    assert!(encoded.len() < pixels.len() / 2);
+    assert_eq!(decode_rl3_bytes(&encoded, 768), pixels);
+
+    let odd_pixels = sample_indexed_pixels(15, 3, 0x07);
+    assert_eq!(decode_rl3_bytes(&encode_rl3_bytes(&odd_pixels, 15), 15), odd_pixels);
+}
+
+#[test]
+fn test_quantize_image() {
+    let source = ImageBuffer::from_fn(32, 32, |x, y| {
+        if x < 4 {
+            Rgba([0, 0, 0, 0])
+        } else {
+            Rgba([(x * 8) as u8, (y * 8) as u8, 64, 255])
+        }
+    });
+    let quantized = quantize_image(QuantizeConfig {
+        image: source.clone(),
+        color_count: 128,
+        use_dithering: false,
+        use_transparency: true,
+        transparency_index: 0,
+        use_lower_indexes: true,
+        alpha_threshold: 128,
+    });
+    assert_eq!(quantized.palette.len(), 128);
+    assert_eq!(quantized.pixels[0], 0);
+    assert!(quantized.pixels[4..32].iter().all(|p| *p != 0));
+
+    let decoded = decode_clut7_image(Clut7Config {
+        width: 32,
+        height: 32,
+        encoded_data: quantized.pixels,
+        clut_data: quantized.palette,
+        use_transparency: true,
+        transparency_index: 0,
+        use_lower_indexes: true,
+    }).unwrap();
+    assert_eq!(decoded.get_pixel(0, 0)[3], 0);
+    assert!(max_channel_difference(&source, &decoded, 4) <= 24);
+}
+
+#[test]
+fn test_quantize_image_dithering() {
+    let source = ImageBuffer::from_fn(64, 8, |x, _| Rgba([(x * 4) as u8, (x * 4) as u8, (x * 4) as u8, 255]));
+    let quantized = quantize_image(QuantizeConfig {
+        image: source,
+        color_count: 16,
+        use_dithering: true,
+        use_transparency: true,
+        transparency_index: 8,
+        use_lower_indexes: false,
+        alpha_threshold: 128,
+    });
+    assert_eq!(quantized.palette.len(), 16);
+    assert!(quantized.pixels.iter().all(|p| *p > 0 && *p < 8));
+    assert_eq!(quantized.palette[12][3], 0);
+}
+
+#[test]
+fn test_clut_commands() {
+    let mut clut = ClutState::new();
+    let commands = [
+        0xC3, 0x00, 0x00, 0x01,
+        0x80, 0x10, 0x20, 0x30,
+        0xBF, 0x40, 0x50, 0x60,
+        // unrelated display control instructions are ignored
+        0x10, 0x00, 0x00, 0x00,
+        0xC3, 0x00, 0x00, 0x00,
+        0x85, 0x70, 0x80, 0x90,
+    ];
+    assert_eq!(clut.apply_commands(&commands), 3);
+    assert_eq!(clut.bank(), 0);
+    assert_eq!(clut.color(64), Rgba([0x10, 0x20, 0x30, 255]));
+    assert_eq!(clut.color(127), Rgba([0x40, 0x50, 0x60, 255]));
+    assert_eq!(clut.color(5), Rgba([0x70, 0x80, 0x90, 255]));
+
+    // a partial update only replaces the entries it loads
+    assert_eq!(clut.apply_commands(&[0xC3, 0x00, 0x00, 0x01, 0x80, 0xFF, 0xFF, 0xFF]), 1);
+    assert_eq!(clut.color(64), Rgba([0xFF, 0xFF, 0xFF, 255]));
+    assert_eq!(clut.color(127), Rgba([0x40, 0x50, 0x60, 255]));
+    assert!(clut.is_loaded(5));
+    assert!(!clut.is_loaded(6));
+    assert_eq!(clut.loaded_count(), 3);
+    assert_eq!(clut.colors().len(), 256);
+}
+
+#[test]
+fn test_palette_export_round_trip() {
+    let palette_data: Vec<u8> = (0..384).map(|i| (i * 7 % 256) as u8).collect();
+    let palette = read_unindexed_palette(&palette_data);
+    let directory = std::env::temp_dir();
+    let path = |extension: &str| directory.join(format!("og_lib_cdi_palette.{extension}")).to_string_lossy().to_string();
+
+    write_act_palette(&path("act"), &palette).unwrap();
+    assert_eq!(std::fs::metadata(path("act")).unwrap().len(), 772);
+    assert_eq!(read_act_palette(&path("act")).unwrap(), palette);
+
+    write_gpl_palette(&path("gpl"), &palette).unwrap();
+    assert_eq!(read_gpl_palette(&path("gpl")).unwrap(), palette);
+
+    write_jasc_palette(&path("pal"), &palette).unwrap();
+    assert_eq!(read_jasc_palette(&path("pal")).unwrap(), palette);
+    std::fs::write(path("pal"), "JASC-PAL\r\n0100\r\n3\r\n1 2 3\r\n4 5 6\r\n").unwrap();
+    assert!(read_jasc_palette(&path("pal")).is_err());
+
+    write_hex_palette(&path("hex"), &palette).unwrap();
+    assert_eq!(read_hex_palette(&path("hex")).unwrap(), palette);
+}
+
+#[test]
+fn test_act_palette_transparency() {
+    let mut clut = ClutState::new();
+    clut.apply_commands(&[0x80, 0x11, 0x22, 0x33]);
+    let mut palette = clut.colors().to_vec();
+    palette[3] = Rgba([0, 0, 0, 0]);
+    let path = std::env::temp_dir().join("og_lib_cdi_transparent.act").to_string_lossy().to_string();
+    write_act_palette(&path, &palette).unwrap();
+    let read = read_act_palette(&path).unwrap();
+    assert_eq!(read.len(), 256);
+    assert_eq!(read[0], Rgba([0x11, 0x22, 0x33, 255]));
+    assert_eq!(read[3][3], 0);
+
+    // only the first transparent colour is recorded
+    palette[7] = Rgba([0, 0, 0, 0]);
+    write_act_palette(&path, &palette).unwrap();
+    let read = read_act_palette(&path).unwrap();
+    assert_eq!((read[3][3], read[7][3]), (0, 255));
+}
+
+const DATA_SUBMODE: u8 = 0b0000_1000;
+
+fn pseudo_random_bytes(length: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..length)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn test_palette_discovery() {
+    let mut clut_commands = vec![0xC3, 0x00, 0x00, 0x01];
+    for i in 0..64u8 {
+        clut_commands.extend([0x80 + i, i * 4, 255 - i * 4, 128]);
+    }
+    let mut rgb_palette = vec![0x00, 0x00, 0x01, 0x80];
+    for i in 0..128u8 {
+        rgb_palette.extend([i * 2, i, 255 - i]);
+    }
+    let mut indexed_palette = Vec::new();
+    for i in 0..32u8 {
+        indexed_palette.extend([i, i * 8, i * 8, 0]);
+    }
+
+    let file = write_test_file(
+        "og_lib_cdi_palette_discovery.rtf",
+        &[
+            build_sector(1, 0, DATA_SUBMODE, 0, &pseudo_random_bytes(2048, 7)),
+            build_sector(1, 0, DATA_SUBMODE, 0, &clut_commands),
+            build_sector(1, 0, DATA_SUBMODE, 0, &rgb_palette),
+            build_sector(1, 0, DATA_SUBMODE, 0, &indexed_palette),
+        ],
+    );
+
+    let candidates = find_palette_candidates(&file, 0.5);
+    let found: Vec<(u32, usize, CdiPaletteType, usize)> = candidates
+        .iter()
+        .map(|c| (c.sector_index, c.offset, c.palette_type, c.colors.len()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (1, 0, CdiPaletteType::Clut, 64),
+            (2, 4, CdiPaletteType::RGB, 128),
+            (3, 0, CdiPaletteType::Indexed, 32),
+        ]
+    );
+    assert_eq!(candidates[1].colors[1], Rgba([2, 1, 254, 255]));
+    assert!(candidates.iter().all(|c| c.confidence >= 0.5));
+
+    // The bank 1 load keeps its position in the CLUT and can be replayed
+    assert_eq!(candidates[0].start_index, 64);
+    assert_eq!(candidates[0].commands, clut_commands);
+    let mut clut = ClutState::new();
+    clut.apply_commands(&candidates[0].commands);
+    assert_eq!(clut.color(64), candidates[0].colors[0]);
+    assert_eq!(candidates[2].start_index, 0);
+    assert!(candidates[2].commands.is_empty());
+}
+
+#[test]
+fn test_palette_discovery_entry_bounds() {
+    // Indexed entries after a bank select do not include the bank select as a colour
+    let mut indexed_palette = vec![0xC3, 0x00, 0x00, 0x01];
+    for i in 0..32u8 {
+        indexed_palette.extend([0x10 + i, i * 8, 100, 50]);
+    }
+    let candidates = find_palettes_in_data(&indexed_palette, 0.5);
+    assert_eq!(candidates[0].palette_type, CdiPaletteType::Indexed);
+    assert_eq!(candidates[0].colors.len(), 32);
+    assert_eq!(candidates[0].colors[0], Rgba([0, 100, 50, 255]));
+
+    // Colour loads end at 0xBF, even when the bytes after them continue the sequence
+    let mut clut_commands = vec![0xC3, 0x00, 0x00, 0x00];
+    for i in 0..64u8 {
+        clut_commands.extend([0x80 + i, i * 4, 255 - i * 4, 128]);
+    }
+    clut_commands.extend([0xC0, 0x00, 0x00, 0x00, 0xC1, 0x00, 0x00, 0x00]);
+    let candidates = find_palettes_in_data(&clut_commands, 0.5);
+    assert_eq!(candidates[0].palette_type, CdiPaletteType::Clut);
+    assert_eq!(candidates[0].length, 4 + 64 * 4);
+    assert_eq!(candidates[0].colors.len(), 64);
+}
+
+fn pad_to_sectors(mut record: Vec<u8>) -> Vec<u8> {
+    record.resize(record.len().div_ceil(2324) * 2324, 0);
+    record
+}
+
+#[test]
+fn test_guess_dimensions_dyuv() {
+    let source = ImageBuffer::from_fn(384, 280, |x, y| Rgba([(x / 2) as u8, (y / 2) as u8, 90, 255]));
+    let encoded = encode_dyuv_image(DyuvEncoderConfig {
+        image: source,
+        initial_y: 16,
+        initial_u: 128,
+        initial_v: 128,
+        use_line_start_values: false,
+    });
+    let record = pad_to_sectors(encoded.encoded_data);
+    let candidates = guess_image_dimensions(&record, CdiCodingInfo::new(0x05));
+    assert_eq!((candidates[0].width, candidates[0].height), (384, 280));
+}
+
+#[test]
+fn test_guess_dimensions_clut7() {
+    let pixels: Vec<u8> = (0..384 * 240).map(|i| ((i % 384) / 3) as u8).collect();
+    let record = pad_to_sectors(pixels);
+    let candidates = guess_image_dimensions(&record, CdiCodingInfo::new(0x01));
+    assert_eq!((candidates[0].width, candidates[0].height), (384, 240));
+}
+
+#[test]
+fn test_guess_dimensions_rle() {
+    let pixels = sample_indexed_pixels(384, 240, 0x7F);
+    let record = pad_to_sectors(encode_rl7_bytes(&pixels, 384));
+    let candidates = guess_image_dimensions(&record, CdiCodingInfo::new(0x04));
+    assert_eq!((candidates[0].width, candidates[0].height), (384, 240));
+
+    // double resolution RL3
+    let pixels = sample_indexed_pixels(768, 280, 0x07);
+    let record = pad_to_sectors(encode_rl3_bytes(&pixels, 768));
+    let candidates = guess_image_dimensions(&record, CdiCodingInfo::new(0x13));
+    assert_eq!((candidates[0].width, candidates[0].height), (768, 280));
+
+    assert!(guess_image_dimensions(&record, CdiCodingInfo::new(0x0F)).is_empty());
+}
+
+fn build_record_sectors(coding: u8, data: &[u8]) -> Vec<Vec<u8>> {
+    data.chunks(2324)
+        .map(|chunk| build_sector(1, 1, VIDEO_SUBMODE, coding, chunk))
+        .collect()
+}
+
+#[test]
+fn test_decode_video_record() {
+    let clut7_pixels: Vec<u8> = (0..384 * 240).map(|i| ((i % 384) / 3) as u8).collect();
+    let rl7_pixels = sample_indexed_pixels(384, 240, 0x7F);
+    let mut sectors = build_record_sectors(0x01, &clut7_pixels);
+    let rl7_start = sectors.len();
+    sectors.extend(build_record_sectors(0x04, &encode_rl7_bytes(&rl7_pixels, 384)));
+    let mpeg_start = sectors.len();
+    sectors.extend(build_record_sectors(0x0F, &[0x00, 0x00, 0x01, 0xB3]));
+    let file = write_test_file("og_lib_cdi_decode_record.rtf", &sectors);
+    let all_sectors: Vec<&CdiSector> = file.sectors().iter().collect();
+
+    let context = DecodeContext::new(grey_palette(128));
+    let image = decode_video_record(&all_sectors[..rl7_start], &context).unwrap();
+    assert_eq!(image.dimensions(), (384, 240));
+    assert_eq!(image.get_pixel(9, 100)[0], 3);
+
+    let image = decode_video_record(&all_sectors[rl7_start..mpeg_start], &context).unwrap();
+    assert_eq!(image.dimensions(), (384, 240));
+    for (x, y, pixel) in image.enumerate_pixels() {
+        assert_eq!(pixel[0], rl7_pixels[(y * 384 + x) as usize]);
+    }
+
+    assert_eq!(
+        decode_video_record(&all_sectors[mpeg_start..], &context).unwrap_err(),
+        DecodeError::UnsupportedCoding(15)
+    );
+    assert_eq!(
+        decode_video_record(&all_sectors[..rl7_start], &DecodeContext::default()).unwrap_err(),
+        DecodeError::MissingClut
+    );
+}
+
+#[test]
+fn test_decode_video_record_ignores_other_sectors() {
+    let pixels: Vec<u8> = (0..384 * 240).map(|i| ((i % 384) / 3) as u8).collect();
+    let mut sectors = build_record_sectors(0x01, &pixels);
+    // A data sector's coding of 0 would be read as CLUT4 and an audio sector's 1 as CLUT7
+    sectors.insert(0, build_sector(1, 1, DATA_SUBMODE, 0x00, &[0xFF; 2048]));
+    sectors.insert(5, build_sector(1, 1, AUDIO_SUBMODE, 0x01, &[0xFF; 2324]));
+    let file = write_test_file("og_lib_cdi_decode_mixed_record.rtf", &sectors);
+    let all_sectors: Vec<&CdiSector> = file.sectors().iter().collect();
+
+    let image = decode_video_record(&all_sectors, &DecodeContext::new(grey_palette(128))).unwrap();
+    assert_eq!(image.dimensions(), (384, 240));
+    for (x, y, pixel) in image.enumerate_pixels() {
+        assert_eq!(pixel[0], pixels[(y * 384 + x) as usize]);
+    }
+}
+
+#[test]
+fn test_decoders_reject_short_input() {
+    let clut_config = |encoded_data: Vec<u8>, clut_data: Vec<Rgba<u8>>| Clut7Config {
+        width: 384,
+        height: 240,
+        encoded_data,
+        clut_data,
+        use_transparency: false,
+        transparency_index: 0,
+        use_lower_indexes: false,
+    };
+    assert_eq!(
+        decode_clut7_image(clut_config(vec![1; 100], grey_palette(128))).unwrap_err(),
+        DecodeError::InsufficientData { expected: 384 * 240, actual: 100 }
+    );
+    assert_eq!(
+        decode_clut8_image(clut_config(vec![1; 384 * 240], Vec::new())).unwrap_err(),
+        DecodeError::MissingClut
+    );
+    assert_eq!(
+        decode_dyuv_image(DyuvImageConfig {
+            width: 384,
+            height: 240,
+            encoded_data: vec![0x55; 1000],
+            initial_y: 16,
+            initial_u: 128,
+            initial_v: 128,
+        })
+        .unwrap_err(),
+        DecodeError::InsufficientData { expected: 384 * 240, actual: 1000 }
+    );
+    assert!(decode_rgb555_image(Rgb555Config {
+        width: 4,
+        height: 4,
+        lower_data: vec![0; 16],
+        upper_data: vec![0; 15],
+        use_transparency: false,
+        transparent_bit_set: false,
+    })
+    .is_err());
+}
+
+#[test]
+fn test_rle_decoders_tolerate_garbage() {
+    let garbage = pseudo_random_bytes(4096, 3);
+    let rle_config = |line_width: usize, height: usize| RleImageConfig {
+        encoded_data: garbage.clone(),
+        line_width,
+        height,
+        clut_data: grey_palette(128),
+        use_transparency: false,
+    };
+    assert_eq!(decode_rle_image(rle_config(384, 280)).unwrap().dimensions(), (384, 280));
+    assert_eq!(decode_rl3_image(rle_config(768, 280)).unwrap().dimensions(), (768, 280));
+    assert!(decode_rl3_image(rle_config(768, 0)).is_ok());
+    assert_eq!(
+        decode_rle_image(rle_config(0, 280)).unwrap_err(),
+        DecodeError::InvalidDimensions { width: 0, height: 280 }
+    );
+    assert!(decode_rle_bytes(&garbage, 0).is_empty());
+    assert_eq!(
+        decode_rle_image(rle_config(384, u32::MAX as usize)).unwrap_err(),
+        DecodeError::InvalidDimensions { width: 384, height: u32::MAX }
+    );
+
+    // Dimensions whose pixel count overflows are rejected rather than wrapping
+    let overflow = DecodeError::InvalidDimensions { width: u32::MAX, height: u32::MAX };
+    let clut_config = Clut7Config {
+        width: u32::MAX,
+        height: u32::MAX,
+        encoded_data: garbage.clone(),
+        clut_data: grey_palette(128),
+        use_transparency: false,
+        transparency_index: 0,
+        use_lower_indexes: false,
+    };
+    assert_eq!(decode_clut7_image(clut_config).unwrap_err(), overflow);
+    let dyuv_config = DyuvImageConfig {
+        width: u32::MAX,
+        height: u32::MAX,
+        encoded_data: garbage.clone(),
+        initial_y: 16,
+        initial_u: 128,
+        initial_v: 128,
+    };
+    assert_eq!(decode_dyuv_image(dyuv_config).unwrap_err(), overflow);
+    let qhy_config = QhyImageConfig {
+        width: u32::MAX,
+        height: u32::MAX,
+        dyuv_data: garbage.clone(),
+        qhy_data: garbage.clone(),
+        initial_y: 16,
+        initial_u: 128,
+        initial_v: 128,
+    };
+    assert_eq!(decode_qhy_image(qhy_config).unwrap_err(), overflow);
+}
+
+fn composite_test_config(plane_order: PlaneOrder, use_mixing: bool) -> CompositeConfig {
+    // plane A is double resolution with a transparent left half, plane B is normal resolution
+    let plane_a = ImageBuffer::from_fn(8, 2, |x, _| if x < 4 { Rgba([0, 0, 0, 0]) } else { Rgba([200, 0, 0, 255]) });
+    let plane_b = ImageBuffer::from_fn(4, 2, |x, _| if x == 0 { Rgba([9, 9, 9, 255]) } else { Rgba([0, 100, 0, 255]) });
+    CompositeConfig {
+        plane_a,
+        plane_b,
+        plane_a_settings: PlaneSettings::default(),
+        plane_b_settings: PlaneSettings {
+            transparency: TransparencyMode::ColorKey(Rgba([9, 9, 9, 255])),
+            image_contribution: 63,
+        },
+        plane_order,
+        use_mixing,
+        backdrop: backdrop_color(0b1001),
+        mattes: Vec::new(),
+    }
+}
+
+#[test]
+fn test_composite_planes() {
+    let frame = composite_planes(composite_test_config(PlaneOrder::AInFront, false));
+    assert_eq!(frame.dimensions(), (8, 2));
+    assert_eq!(*frame.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+    assert_eq!(*frame.get_pixel(2, 0), Rgba([0, 100, 0, 255]));
+    assert_eq!(*frame.get_pixel(6, 1), Rgba([200, 0, 0, 255]));
+
+    let frame = composite_planes(composite_test_config(PlaneOrder::BInFront, false));
+    assert_eq!(*frame.get_pixel(6, 1), Rgba([0, 100, 0, 255]));
+
+    let frame = composite_planes(composite_test_config(PlaneOrder::AInFront, true));
+    assert_eq!(*frame.get_pixel(6, 1), Rgba([200, 100, 0, 255]));
+    assert_eq!(*frame.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+}
+
+#[test]
+fn test_composite_mattes_and_contribution() {
+    let mut config = composite_test_config(PlaneOrder::AInFront, false);
+    config.plane_a_settings = PlaneSettings {
+        transparency: TransparencyMode::MatteFlag0,
+        image_contribution: 21,
+    };
+    config.mattes = vec![Matte { x_start: 0, x_end: 2, y_start: 1, y_end: 2 }];
+    let frame = composite_planes(config);
+    // inside the matte, plane A is opaque even where it was decoded as transparent
+    assert_eq!(*frame.get_pixel(0, 1), Rgba([0, 0, 0, 255]));
+    assert_eq!(*frame.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+    assert_eq!(*frame.get_pixel(6, 1), Rgba([0, 100, 0, 255]));
+    assert_eq!(backdrop_color(0b0110), Rgba([128, 128, 0, 255]));
+
+    let mut config = composite_test_config(PlaneOrder::AInFront, false);
+    config.plane_a_settings.image_contribution = 21;
+    assert_eq!(*composite_planes(config).get_pixel(6, 1), Rgba([66, 0, 0, 255]));
+}
+
+fn instruction_bytes(instructions: &[u32]) -> Vec<u8> {
+    instructions.iter().flat_map(|i| i.to_be_bytes()).collect()
+}
+
+#[test]
+fn test_parse_display_instructions() {
+    let instructions = parse_display_instructions(&instruction_bytes(&[0x1000_0000, 0x4000_1000, 0xC100_0201, 0x8512_3456, 0x0000_0000]));
+    assert_eq!(
+        instructions,
+        vec![
+            DisplayInstruction::Nop,
+            DisplayInstruction::ReloadVsr(0x1000),
+            DisplayInstruction::LoadRegister { register: 0xC1, value: 0x0201 },
+            DisplayInstruction::LoadRegister { register: 0x85, value: 0x12_3456 },
+            DisplayInstruction::Stop,
+        ]
+    );
+}
+
+#[test]
+fn test_simulate_display_program() {
+    let frame = instruction_bytes(&[0xC300_0000, 0x8000_0000, 0x8100_FFFF, 0xC100_0102, 0xC200_0001, 0x0000_0000]);
+    let table = instruction_bytes(&[
+        // line 0: nothing changes
+        0x1000_0000, 0x1000_0000,
+        // line 1: entry 0 turns red, the load after the stop is ignored
+        0x80FF_0000, 0x0000_0000,
+        // line 2: the image restarts at the start of plane memory, DYUV start values change
+        0x4000_0000, 0xCA80_8080,
+    ]);
+    let program = DisplayControlProgram::from_line_control_table(&frame, &table, 2);
+    let lines = simulate_display_program(&program, 4, DisplayParameters::default());
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0].clut.color(0), Rgba([0, 0, 0, 255]));
+    assert_eq!(lines[1].clut.color(0), Rgba([255, 0, 0, 255]));
+    assert_eq!(lines[3].clut.color(0), Rgba([255, 0, 0, 255]));
+    assert_eq!(lines[2].image_start, Some(0));
+    assert_eq!(lines[3].image_start, None);
+    assert_eq!(lines[3].dyuv_start_a.y, 0x80);
+    assert_eq!(lines[0].transparency_mode_a(), TransparencyMode::TransparentBit);
+    assert_eq!(lines[0].plane_order(), PlaneOrder::BInFront);
+
+    let memory = vec![0, 1, 0, 1, 1, 1];
+    let image = render_clut_lines(&memory, 0, 2, &lines).unwrap();
+    assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+    assert_eq!(*image.get_pixel(0, 1), Rgba([255, 0, 0, 255]));
+    assert_eq!(*image.get_pixel(1, 1), Rgba([0, 255, 255, 255]));
+    // line 2 restarts at offset 0, line 3 continues after it
+    assert_eq!(*image.get_pixel(1, 2), Rgba([0, 255, 255, 255]));
+    assert_eq!(*image.get_pixel(0, 3), Rgba([255, 0, 0, 255]));
+
+    let rle = encode_rl7_bytes(&[0, 1, 1, 1, 1, 0, 0, 0], 4);
+    let image = render_rl7_lines(&rle, 0, 4, &lines).unwrap();
+    assert_eq!(*image.get_pixel(3, 0), Rgba([0, 255, 255, 255]));
+    assert_eq!(*image.get_pixel(1, 1), Rgba([255, 0, 0, 255]));
+    assert_eq!(*image.get_pixel(1, 2), Rgba([0, 255, 255, 255]));
+
+    let image = render_dyuv_lines(vec![0; 16], 4, &lines).unwrap();
+    assert_ne!(image.get_pixel(0, 0), image.get_pixel(0, 3));
+}
+
+#[test]
+fn test_scale_to_resolution() {
+    let image = ImageBuffer::from_fn(4, 2, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+    let double = scale_to_resolution(&image, CdiResolution::Normal, CdiResolution::Double);
+    assert_eq!(double.dimensions(), (8, 2));
+    assert_eq!(double.get_pixel(5, 1), &Rgba([2, 1, 0, 255]));
+
+    let high = scale_to_resolution(&image, CdiResolution::Normal, CdiResolution::High);
+    assert_eq!(high.dimensions(), (8, 4));
+    assert_eq!(high.get_pixel(7, 3), &Rgba([3, 1, 0, 255]));
+
+    let normal = scale_to_resolution(&high, CdiResolution::High, CdiResolution::Normal);
+    assert_eq!(normal.dimensions(), (4, 2));
+}
+
+#[test]
+fn test_correct_aspect_ratio() {
+    let image = ImageBuffer::from_pixel(384, 240, Rgba([10, 20, 30, 255]));
+    let ntsc = correct_aspect_ratio(&image, CdiResolution::Normal, VideoStandard::Ntsc, FilterType::Nearest);
+    assert_eq!(ntsc.dimensions(), (384, 293));
+    let pal = correct_aspect_ratio(&image, CdiResolution::Normal, VideoStandard::Pal, FilterType::Nearest);
+    assert_eq!(pal.dimensions(), (384, 244));
+
+    let double = ImageBuffer::from_pixel(768, 280, Rgba([10, 20, 30, 255]));
+    let pal = correct_aspect_ratio(&double, CdiResolution::Double, VideoStandard::Pal, FilterType::Nearest);
+    assert_eq!(pal.dimensions(), (768, 569));
+    let high = ImageBuffer::from_pixel(768, 560, Rgba([10, 20, 30, 255]));
+    let pal = correct_aspect_ratio(&high, CdiResolution::High, VideoStandard::Pal, FilterType::Nearest);
+    assert_eq!(pal.dimensions(), (768, 569));
+}
+
+#[test]
+fn test_decode_video_record_resolution() {
+    let pixels: Vec<u8> = (0..384 * 240).map(|i| ((i % 384) / 3) as u8).collect();
+    let sectors = build_record_sectors(0x01, &pixels);
+    let file = write_test_file("og_lib_cdi_decode_resolution.rtf", &sectors);
+    let all_sectors: Vec<&CdiSector> = file.sectors().iter().collect();
+    assert_eq!(all_sectors[0].coding_info().resolution_type(), CdiResolution::Normal);
+
+    let context = DecodeContext {
+        display_resolution: Some(CdiResolution::Double),
+        ..DecodeContext::new(grey_palette(128))
+    };
+    let image = decode_video_record(&all_sectors, &context).unwrap();
+    assert_eq!(image.dimensions(), (768, 240));
+    assert_eq!(image.get_pixel(19, 100)[0], 3);
+
+    let context = DecodeContext {
+        aspect_correction: Some(VideoStandard::Ntsc),
+        ..context
+    };
+    let image = decode_video_record(&all_sectors, &context).unwrap();
+    assert_eq!(image.dimensions(), (768, 587));
+}
+
+#[test]
+fn test_weave_fields() {
+    let even = ImageBuffer::from_pixel(4, 2, Rgba([10, 10, 10, 255]));
+    let odd = ImageBuffer::from_pixel(4, 2, Rgba([20, 20, 20, 255]));
+    let frame = weave_fields(Some(&even), Some(&odd)).unwrap();
+    assert_eq!(frame.dimensions(), (4, 4));
+    let column: Vec<u8> = (0..4).map(|y| frame.get_pixel(0, y)[0]).collect();
+    assert_eq!(column, vec![10, 20, 10, 20]);
+
+    let doubled = weave_fields(None, Some(&odd)).unwrap();
+    assert_eq!(doubled.dimensions(), (4, 4));
+    assert!(doubled.pixels().all(|p| p[0] == 20));
+
+    let short = ImageBuffer::from_pixel(4, 1, Rgba([20, 20, 20, 255]));
+    assert!(weave_fields(Some(&even), Some(&short)).is_err());
+    assert!(weave_fields(None, None).is_err());
+}
+
+#[test]
+fn test_field_pairing() {
+    const EOR: u8 = VIDEO_SUBMODE | 1;
+    const ODD_LINES: u8 = 0x40;
+    let field = |channel: u8, coding: u8, value: u8| build_sector(1, channel, EOR, coding, &[value; 8]);
+    let sectors = vec![
+        field(1, 0x01, 1),
+        field(2, 0x01 | ODD_LINES, 2),
+        field(1, 0x01 | ODD_LINES, 3),
+        field(2, 0x01, 4),
+        field(1, 0x01, 5),
+        field(1, 0x01, 6),
+        field(1, 0x01 | ODD_LINES, 7),
+    ];
+    let file = write_test_file("og_lib_cdi_field_pairing.rtf", &sectors);
+    let records = file.get_video_records();
+    assert_eq!(records.len(), 7);
+
+    let pairs = pair_field_records(&records);
+    let summary: Vec<(u8, Option<u32>, Option<u32>)> = pairs
+        .iter()
+        .map(|p| {
+            let index = |r: &Option<Vec<&CdiSector>>| r.as_ref().map(|r| r[0].sector_index());
+            (p.channel, index(&p.even), index(&p.odd))
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![(1, Some(0), Some(2)), (2, Some(3), Some(1)), (1, Some(4), None), (1, Some(5), Some(6))]
+    );
+
+    let context = DecodeContext {
+        width: 4,
+        height: 2,
+        ..DecodeContext::new(grey_palette(128))
+    };
+    let frame = decode_field_pair(&pairs[0], &context).unwrap();
+    assert_eq!(frame.dimensions(), (4, 4));
+    let column: Vec<u8> = (0..4).map(|y| frame.get_pixel(0, y)[0]).collect();
+    assert_eq!(column, vec![1, 3, 1, 3]);
+
+    let frame = decode_field_pair(&pairs[2], &context).unwrap();
+    assert_eq!(frame.dimensions(), (4, 4));
+    assert!(frame.pixels().all(|p| p[0] == 5));
+}
+
+fn animation_frames() -> Vec<AnimationFrame> {
+    (0..3u32)
+        .map(|i| AnimationFrame {
+            image: ImageBuffer::from_fn(6, 4, |x, y| Rgba([(x * 40) as u8, (y * 60) as u8, (i * 100) as u8, 255])),
+            delay_ms: 40 + i * 20,
+        })
+        .collect()
+}
+
+fn animation_path(name: &str) -> String {
+    std::env::temp_dir().join(name).to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_animation_format_from_path() {
+    assert_eq!(AnimationFormat::from_path("intro.GIF"), Some(AnimationFormat::Gif));
+    assert_eq!(AnimationFormat::from_path("intro.apng"), Some(AnimationFormat::Apng));
+    assert_eq!(AnimationFormat::from_path("intro.webp"), Some(AnimationFormat::WebP));
+    assert_eq!(AnimationFormat::from_path("intro"), None);
+}
+
+#[test]
+fn test_apng_animation() {
+    let frames = animation_frames();
+    let path = animation_path("og_lib_cdi_animation.png");
+    let config = AnimationConfig { format: AnimationFormat::Apng, width: 6, height: 4, loop_count: 3 };
+    write_animation(&path, &frames, config).unwrap();
+
+    let mut reader = png::Decoder::new(File::open(&path).unwrap()).read_info().unwrap();
+    let control = reader.info().animation_control.unwrap();
+    assert_eq!((control.num_frames, control.num_plays), (3, 3));
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    for frame in &frames {
+        reader.next_frame(&mut buffer).unwrap();
+        let frame_control = reader.info().frame_control.unwrap();
+        assert_eq!((frame_control.delay_num, frame_control.delay_den), (frame.delay_ms as u16, 1000));
+        assert_eq!(buffer, *frame.image.as_raw());
+    }
+}
+
+#[test]
+fn test_webp_animation() {
+    let frames = animation_frames();
+    let path = animation_path("og_lib_cdi_animation.webp");
+    let config = AnimationConfig { format: AnimationFormat::WebP, width: 6, height: 4, loop_count: 0 };
+    write_animation(&path, &frames, config).unwrap();
+    let data = std::fs::read(&path).unwrap();
+    let animation = data.windows(4).position(|w| w == b"ANIM").unwrap();
+    assert_eq!(data[animation + 12..animation + 14], [0, 0]);
+
+    let mut decoder = image_webp::WebPDecoder::new(std::io::BufReader::new(File::open(&path).unwrap())).unwrap();
+    assert!(decoder.is_animated());
+    assert_eq!(decoder.num_frames(), 3);
+    let mut buffer = vec![0; decoder.output_buffer_size().unwrap()];
+    for frame in &frames {
+        assert_eq!(decoder.read_frame(&mut buffer).unwrap(), frame.delay_ms);
+        assert_eq!(buffer, *frame.image.as_raw());
+    }
+}
+
+#[test]
+fn test_gif_animation() {
+    let mut frames = animation_frames();
+    frames.push(AnimationFrame { image: ImageBuffer::from_pixel(3, 2, Rgba([255, 0, 0, 255])), delay_ms: 1000 });
+    let path = animation_path("og_lib_cdi_animation.gif");
+    let config = AnimationConfig { format: AnimationFormat::Gif, width: 6, height: 4, loop_count: 2 };
+    write_animation(&path, &frames, config).unwrap();
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+    let mut delays = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!((frame.width, frame.height), (6, 4));
+        delays.push(frame.delay);
+    }
+    assert_eq!(delays, vec![4, 6, 8, 100]);
+    assert_eq!(decoder.repeat(), gif::Repeat::Finite(1));
+}
+
+#[test]
+fn test_frame_timings() {
+    const EOR: u8 = VIDEO_SUBMODE | 1;
+    const TRIGGER: u8 = 1 << 4;
+    let mut sectors = Vec::new();
+    for (start, record_length, trigger) in [(0, 1, false), (15, 3, true), (45, 2, false)] {
+        while sectors.len() < start {
+            sectors.push(build_sector(1, 0, DATA_SUBMODE, 0, &[]));
+        }
+        for i in 0..record_length {
+            let submode = match i {
+                i if i == record_length - 1 && trigger => VIDEO_SUBMODE | TRIGGER,
+                i if i == record_length - 1 => EOR,
+                _ => VIDEO_SUBMODE,
+            };
+            sectors.push(build_sector(1, 1, submode, 0x01, &[0; 8]));
+        }
+    }
+    let file = write_test_file("og_lib_cdi_frame_timings.rtf", &sectors);
+    let records = file.get_video_records();
+
+    let timings = frame_timings(&records, 1);
+    let summary: Vec<(u32, u32, u32)> = timings.iter().map(|t| (t.sector_index, t.start_ms, t.duration_ms)).collect();
+    assert_eq!(summary, vec![(0, 0, 226), (17, 226, 387), (46, 613, 387)]);
+
+    let double_speed = frame_timings(&records, 2);
+    assert_eq!(double_speed[1].start_ms, 113);
+
+    let images = vec![ImageBuffer::from_pixel(2, 2, Rgba([0, 0, 0, 255])); 3];
+    let frames = timed_frames(images, &timings);
+    assert_eq!(frames.iter().map(|f| f.delay_ms).collect::<Vec<_>>(), vec![226, 387, 387]);
+
+    // An empty record keeps its place, so the frames after it keep their own delays
+    let with_empty = vec![records[0].clone(), Vec::new(), records[1].clone(), records[2].clone(), Vec::new()];
+    let timings_with_empty = frame_timings(&with_empty, 1);
+    assert_eq!(timings_with_empty.len(), 5);
+    assert_eq!((timings_with_empty[1].sector_index, timings_with_empty[1].start_ms), (17, 226));
+    assert_eq!(timings_with_empty[4].start_ms, 613 + 387);
+    let images = vec![ImageBuffer::from_pixel(2, 2, Rgba([0, 0, 0, 255])); 5];
+    let frames = timed_frames(images, &timings_with_empty);
+    assert_eq!(frames.iter().map(|f| f.delay_ms).collect::<Vec<_>>(), vec![226, 0, 387, 387, 0]);
+
+    let json = timeline_json(&timings[..2]);
+    assert_eq!(
+        json,
+        "{\n  \"frames\": [\n    { \"frame\": 0, \"sector\": 0, \"start_ms\": 0, \"duration_ms\": 226 },\n    { \"frame\": 1, \"sector\": 17, \"start_ms\": 226, \"duration_ms\": 387 }\n  ]\n}\n"
+    );
+    assert_eq!(timeline_json(&[]), "{\n  \"frames\": []\n}\n");
+}
+
+const AUDIO_SUBMODE: u8 = 0b100;
+
+/// Builds a level B or C sound group with every sound unit using `parameter` and every data byte set to `data`.
+fn level_bc_sound_group(parameter: u8, data: u8) -> Vec<u8> {
+    let mut group = vec![parameter; 16];
+    group.resize(128, data);
+    group
+}
+
+#[test]
+fn test_decode_audio_sectors() {
+    // Level C mono: the first group decodes nibbles 1 and 2 at range 0, the second is
+    // silent but uses filter 1, so it decays from the last sample of the first group
+    let mut payload = level_bc_sound_group(0x00, 0x21);
+    payload.extend(level_bc_sound_group(0x10, 0x00));
+    payload.resize(2304, 0);
+    let sectors = vec![build_sector(1, 0, AUDIO_SUBMODE, 0b0100, &payload)];
+    let file = write_test_file("og_lib_cdi_audio_level_c.rtf", &sectors);
+    let audio = decode_audio_sectors(&file.get_audio_sectors());
+    assert_eq!((audio.sample_rate, audio.channels), (18900, 1));
+    assert_eq!(audio.samples.len(), 18 * 8 * 28);
+    assert_eq!(audio.samples[0], 4096);
+    assert_eq!(audio.samples[28], 8192);
+    assert_eq!(audio.samples[224], (8192 * 240 / 256) as i16);
+
+    // Level A stereo: units alternate between the left and right channels
+    let mut group = vec![0u8; 16];
+    group.resize(128, 0x01);
+    let payload: Vec<u8> = group.iter().cycle().take(2304).cloned().collect();
+    let sectors = vec![build_sector(1, 0, AUDIO_SUBMODE, 0b0001_0001, &payload)];
+    let file = write_test_file("og_lib_cdi_audio_level_a.rtf", &sectors);
+    let audio = decode_audio_sectors(&file.get_audio_sectors());
+    assert_eq!((audio.sample_rate, audio.channels), (37800, 2));
+    assert_eq!(audio.samples.len(), 18 * 4 * 28);
+    assert!(audio.samples.iter().all(|s| *s == 256));
+
+    let delayed = offset_audio(&audio, 10);
+    assert_eq!(delayed.samples.len(), audio.samples.len() + 378 * 2);
+    assert_eq!(delayed.samples[755], 0);
+    let trimmed = offset_audio(&audio, -10);
+    assert_eq!(trimmed.samples.len(), audio.samples.len() - 378 * 2);
+
+    let path = animation_path("og_lib_cdi_audio.wav");
+    write_wav(&path, &audio).unwrap();
+    let wav = std::fs::read(&path).unwrap();
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 2);
+    assert_eq!(u32::from_le_bytes([wav[24], wav[25], wav[26], wav[27]]), 37800);
+    assert_eq!(wav.len(), 44 + audio.samples.len() * 2);
+}
+
+#[test]
+fn test_adpcm_decoding() {
+    // Expected samples follow the CD-ROM XA decoding of (data << 12) >> range for 4-bit and
+    // (data << 8) >> range for 8-bit data, plus (s1 * K0 + s2 * K1) / 64 with filter 1 using
+    // K0 = 60, on values where the prediction divides exactly.
+    let level_c_group = |parameters: [u8; 8], nibbles: [u8; 8]| {
+        // parameters 0-3 and 4-7 are repeated before and after the full list
+        let mut group = parameters[..4].to_vec();
+        group.extend(parameters);
+        group.extend(&parameters[4..]);
+        for _ in 0..28 {
+            group.extend((0..4).map(|k| nibbles[2 * k] | (nibbles[2 * k + 1] << 4)));
+        }
+        group
+    };
+    // Unit 0 and 7 hold 1 at range 0, units 1 to 6 are silent with filter 1 at range 4, their
+    // filter nibble of 5 having the reserved bit 2 set. The second group only predicts.
+    let mut payload = level_c_group([0x00, 0x54, 0x54, 0x54, 0x54, 0x54, 0x54, 0x00], [1, 0, 0, 0, 0, 0, 0, 1]);
+    payload.extend(level_c_group([0x10; 8], [0; 8]));
+    payload.resize(2304, 0);
+    let sectors = vec![build_sector(1, 0, AUDIO_SUBMODE, 0b0100, &payload)];
+    let file = write_test_file("og_lib_cdi_adpcm_level_c.rtf", &sectors);
+    let samples = decode_audio_sectors(&file.get_audio_sectors()).samples;
+    assert!(samples[..28].iter().all(|s| *s == 4096));
+    assert_eq!(samples[28..31], [3840, 3600, 3375]);
+    assert_eq!(samples[223], 4096);
+    // prediction carries on from the last sample of the previous sound group
+    assert_eq!(samples[224..227], [3840, 3600, 3375]);
+
+    // Level A mono at range 4, unit 0 holding 16 and unit 1 holding -16
+    let mut group = vec![0x04; 16];
+    for _ in 0..28 {
+        group.extend([0x10, 0xF0, 0x10, 0xF0]);
+    }
+    let payload: Vec<u8> = group.iter().cycle().take(2304).cloned().collect();
+    let sectors = vec![build_sector(1, 0, AUDIO_SUBMODE, 0b0001_0000, &payload)];
+    let file = write_test_file("og_lib_cdi_adpcm_level_a.rtf", &sectors);
+    let samples = decode_audio_sectors(&file.get_audio_sectors()).samples;
+    assert!(samples[..28].iter().all(|s| *s == 256));
+    assert!(samples[28..56].iter().all(|s| *s == -256));
+}
+
+#[test]
+fn test_write_y4m() {
+    let frames: Vec<AnimationFrame> = [40, 80, 40]
+        .iter()
+        .map(|delay_ms| AnimationFrame { image: ImageBuffer::from_pixel(4, 2, Rgba([255, 255, 255, 255])), delay_ms: *delay_ms })
+        .collect();
+    let path = animation_path("og_lib_cdi_video.y4m");
+    let config = Y4mConfig { frame_rate: VideoStandard::Pal.frame_rate(), pixel_aspect: VideoStandard::Pal.pixel_aspect_fraction(CdiResolution::Double) };
+    assert_eq!(write_y4m(&path, &frames, config).unwrap(), 4);
+
+    let data = std::fs::read(&path).unwrap();
+    let header = b"YUV4MPEG2 W4 H2 F25:1 Ip A59:120 C444 XCOLORRANGE=LIMITED\n";
+    assert_eq!(&data[..header.len()], header);
+    assert_eq!(data.len(), header.len() + 4 * (6 + 4 * 2 * 3));
+    assert_eq!(data[header.len() + 6], 235);
+}
+
+#[test]
+fn test_export_channel_av() {
+    const EOR: u8 = VIDEO_SUBMODE | 1;
+    let mut audio_payload = level_bc_sound_group(0x00, 0x11);
+    audio_payload.resize(2304, 0);
+    let sectors = vec![
+        build_sector(1, 1, EOR, 0x01, &[1; 8]),
+        build_sector(1, 2, EOR, 0x01, &[2; 8]),
+        build_sector(1, 1, AUDIO_SUBMODE, 0b0100, &audio_payload),
+        build_sector(1, 1, EOR, 0x01, &[3; 8]),
+        build_sector(1, 1, EOR, 0x0F, &[0, 0, 1, 0xB3]),
+    ];
+    let file = write_test_file("og_lib_cdi_export_av.rtf", &sectors);
+    let config = AvExportConfig {
+        channel: 1,
+        context: DecodeContext { width: 4, height: 2, ..DecodeContext::new(grey_palette(128)) },
+        standard: VideoStandard::Ntsc,
+        speed: 1,
+    };
+    let y4m_path = animation_path("og_lib_cdi_export_av.y4m");
+    let wav_path = animation_path("og_lib_cdi_export_av.wav");
+    let summary = export_channel_av(&file, &config, &y4m_path, Some(&wav_path)).unwrap();
+
+    assert_eq!((summary.decoded_records, summary.skipped_records), (2, 0));
+    assert_eq!(summary.audio_offset_ms, 26);
+    assert_eq!(summary.audio_samples, 18900 * 26 / 1000 + 18 * 8 * 28);
+    // Frames at sectors 0 and 3 last 40ms each
+    assert_eq!(summary.video_frames, 2);
+    let data = std::fs::read(&y4m_path).unwrap();
+    assert!(data.starts_with(b"YUV4MPEG2 W4 H2 F30000:1001 Ip A9:11 "));
+}
+
+fn pes_packet(stream_id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x00, 0x00, 0x01, stream_id];
+    packet.extend_from_slice(&((payload.len() + 1) as u16).to_be_bytes());
+    packet.push(0x0F);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+fn mpeg_pack_sector(submode: u8, packets: &[Vec<u8>], address: u32) -> Vec<u8> {
+    let mut payload = vec![0x00, 0x00, 0x01, 0xBA, 0x21, 0x00, 0x01, 0x00, 0x01, 0x80, 0x00, 0x01];
+    for packet in packets {
+        payload.extend_from_slice(packet);
+    }
+    payload.extend(pes_packet(0xBE, &[0xFF; 16]));
+    let mut sector = build_sector(1, 1, submode, 0x0F, &payload);
+    set_header_address(&mut sector, address);
+    sector
+}
+
+fn set_header_address(sector: &mut [u8], address: u32) {
+    let to_bcd = |value: u32| (((value / 10) << 4) | (value % 10)) as u8;
+    sector[12] = to_bcd(address / 75 / 60);
+    sector[13] = to_bcd(address / 75 % 60);
+    sector[14] = to_bcd(address % 75);
+}
+
+#[test]
+fn test_demux_mpeg() {
+    let sectors = vec![
+        mpeg_pack_sector(VIDEO_SUBMODE, &[pes_packet(0xE0, &[0x00, 0x00, 0x01, 0xB3, 1, 2])], 150),
+        mpeg_pack_sector(AUDIO_SUBMODE, &[pes_packet(0xC0, &[0xFF, 0xFD, 3]), pes_packet(0xC1, &[9, 9])], 151),
+        build_sector(1, 2, VIDEO_SUBMODE, 0x0F, &[0x00, 0x00, 0x01, 0xB3]),
+        mpeg_pack_sector(VIDEO_SUBMODE, &[pes_packet(0xE0, &[4, 5])], 155),
+        build_sector(1, 1, VIDEO_SUBMODE, 0x05, &[1, 2, 3]),
+    ];
+    let file = write_test_file("og_lib_cdi_mpeg.rtf", &sectors);
+
+    let result = demux_mpeg(&file, Some(1));
+    assert_eq!((result.video_sectors, result.audio_sectors), (2, 1));
+    assert_eq!(result.video, vec![0x00, 0x00, 0x01, 0xB3, 1, 2, 4, 5]);
+    assert_eq!(result.audio, vec![0xFF, 0xFD, 3]);
+    assert_eq!(result.gaps, vec![MpegGap { after_sector_index: 1, missing_sectors: 2 }]);
+    assert_eq!(&result.program_stream[..4], &[0x00, 0x00, 0x01, 0xBA]);
+    assert_eq!(&result.program_stream[result.program_stream.len() - 4..], &[0x00, 0x00, 0x01, 0xB9]);
+    let pack_length = 12 + 13 + 23;
+    assert_eq!(result.program_stream.len(), pack_length + (12 + 10 + 9 + 23) + (12 + 9 + 23) + 4);
+
+    // The padding after elementary stream data is dropped, keeping two zeros of stuffing
+    let elementary = demux_mpeg(&file, Some(2));
+    assert_eq!(elementary.video, vec![0x00, 0x00, 0x01, 0xB3, 0x00, 0x00]);
+    assert!(elementary.program_stream.is_empty());
+
+    let base_path = animation_path("og_lib_cdi_mpeg");
+    let paths = write_mpeg_streams(&elementary, &base_path).unwrap();
+    assert_eq!(paths, vec![format!("{}.m1v", base_path)]);
+}
+
+#[test]
+fn test_sprite_sheet() {
+    const EOR: u8 = VIDEO_SUBMODE | 1;
+    let sprite = |height: usize, index: u8| encode_rl7_bytes(&vec![index; 8 * height], 8);
+    let sectors = vec![
+        build_sector(1, 3, EOR, 0x04, &sprite(3, 10)),
+        build_sector(1, 3, EOR, 0x04, &sprite(5, 20)),
+        build_sector(1, 4, EOR, 0x04, &sprite(2, 90)),
+        build_sector(1, 3, VIDEO_SUBMODE, 0x04, &sprite(4, 30)),
+        build_sector(1, 3, EOR, 0x04, &[]),
+        build_sector(1, 3, EOR, 0x04, &[]),
+    ];
+    let file = write_test_file("og_lib_cdi_sprites.rtf", &sectors);
+    let config = SpriteSheetConfig {
+        channel: 3,
+        context: DecodeContext { width: 8, ..DecodeContext::new(grey_palette(128)) },
+        max_width: 20,
+        padding: 1,
+    };
+    let sheet = build_sprite_sheet(&file, &config);
+
+    let placements: Vec<(usize, u32, u32, u32, u32, u32)> = sheet
+        .entries
+        .iter()
+        .map(|e| (e.record_index, e.first_sector, e.last_sector, e.x, e.y, e.height))
+        .collect();
+    assert_eq!(placements, vec![(0, 0, 0, 1, 1, 3), (1, 1, 1, 10, 1, 5), (2, 3, 4, 1, 7, 4)]);
+    assert_eq!(sheet.skipped.len(), 1);
+    assert_eq!(sheet.image.dimensions(), (19, 12));
+    assert_eq!(sheet.image.get_pixel(10, 5)[0], 20);
+    assert_eq!(sheet.image.get_pixel(1, 10)[0], 30);
+    assert_eq!(sheet.image.get_pixel(0, 0)[3], 0);
+
+    let toml = sheet.atlas_toml();
+    assert!(toml.starts_with("width = 19\nheight = 12\n\n[[sprites]]\nrecord = 0\n"));
+    let json = sheet.atlas_json();
+    assert!(json.contains("{ \"record\": 2, \"first_sector\": 3, \"last_sector\": 4, \"x\": 1, \"y\": 7, \"width\": 8, \"height\": 4 }"));
+
+    let png_path = animation_path("og_lib_cdi_sprites.png");
+    let atlas_path = animation_path("og_lib_cdi_sprites.toml");
+    sheet.save(&png_path, &atlas_path).unwrap();
+    assert_eq!(std::fs::read_to_string(&atlas_path).unwrap(), toml);
+    assert_eq!(image::open(&png_path).unwrap().to_rgba8(), sheet.image);
+}
+
+#[test]
+fn test_extract_all_images() {
+    const EOR: u8 = VIDEO_SUBMODE | 1;
+    let mut clut_commands = vec![0xC3, 0x00, 0x00, 0x00];
+    for i in 0..64u8 {
+        clut_commands.extend([0x80 + i, i * 4, 255 - i * 4, 128]);
+    }
+    let sectors = vec![
+        build_sector(1, 1, EOR, 0x01, &[5; 8]),
+        build_sector(1, 0, DATA_SUBMODE, 0, &clut_commands),
+        build_sector(1, 1, EOR, 0x01, &[5; 8]),
+        build_sector(1, 1, EOR, 0x0F, &[0, 0, 1, 0xB3]),
+        build_sector(1, 2, EOR, 0x05, &[0; 8]),
+    ];
+    let file = write_test_file("og_lib_cdi_extract_all.rtf", &sectors);
+    let output_dir = animation_path("og_lib_cdi_extract_all");
+    let mut options = ExtractOptions::new(&output_dir);
+    options.context.width = 4;
+    options.context.height = 2;
+    let records = extract_all_images(&file, &options).unwrap();
+
+    let summary: Vec<(u32, &str, Option<u32>, bool)> = records
+        .iter()
+        .map(|r| (r.first_sector, r.coding.as_str(), r.palette_sector, r.result.is_ok()))
+        .collect();
+    assert_eq!(summary, vec![(0, "CLUT7", None, false), (2, "CLUT7", Some(1), true), (4, "DYUV", Some(1), true)]);
+    assert_eq!(records[1].result, Ok(("image_0000.png".to_string(), 4, 2)));
+
+    let image = image::open(format!("{}/image_0000.png", output_dir)).unwrap().to_rgba8();
+    assert_eq!(*image.get_pixel(0, 0), Rgba([20, 235, 128, 255]));
+    assert!(std::path::Path::new(&format!("{}/image_0001.png", output_dir)).exists());
+
+    let manifest = std::fs::read_to_string(format!("{}/manifest.json", output_dir)).unwrap();
+    assert_eq!(manifest, manifest_json(&records));
+    assert!(manifest.contains("\"palette_sector\": null, \"error\": \"no CLUT colours were supplied\""));
+
+    let mut escaped = records[1].clone();
+    escaped.result = Ok(("a\\\"b.png".to_string(), 4, 2));
+    assert!(manifest_json(&[escaped]).contains("\"file\": \"a\\\\\\\"b.png\""));
+}
+
+#[test]
+fn test_extract_all_images_clut_banks() {
+    const EOR: u8 = VIDEO_SUBMODE | 1;
+    let mut clut_commands = Vec::new();
+    for bank in 0..2u8 {
+        clut_commands.extend([0xC3, 0x00, 0x00, bank]);
+        for i in 0..64u8 {
+            clut_commands.extend([0x80 + i, bank * 100, i, 50]);
+        }
+    }
+    // a later partial update only changes the first colours of bank 1
+    let mut update_commands = vec![0xC3, 0x00, 0x00, 0x01];
+    for i in 0..16u8 {
+        update_commands.extend([0x80 + i, 200, i, 0]);
+    }
+    let sectors = vec![
+        build_sector(1, 0, DATA_SUBMODE, 0, &clut_commands),
+        build_sector(1, 1, EOR, 0x01, &[5, 100, 64, 127]),
+        build_sector(1, 0, DATA_SUBMODE, 0, &update_commands),
+        build_sector(1, 1, EOR, 0x01, &[5, 100, 64, 127]),
+    ];
+    let file = write_test_file("og_lib_cdi_extract_clut_banks.rtf", &sectors);
+    let output_dir = animation_path("og_lib_cdi_extract_clut_banks");
+    let mut options = ExtractOptions::new(&output_dir);
+    options.context.width = 4;
+    options.context.height = 1;
+    let records = extract_all_images(&file, &options).unwrap();
+    assert_eq!(records.iter().map(|r| r.palette_sector).collect::<Vec<_>>(), vec![Some(0), Some(2)]);
+
+    let image = image::open(format!("{}/image_0000.png", output_dir)).unwrap().to_rgba8();
+    let pixels: Vec<Rgba<u8>> = image.pixels().cloned().collect();
+    assert_eq!(
+        pixels,
+        vec![Rgba([0, 5, 50, 255]), Rgba([100, 36, 50, 255]), Rgba([100, 0, 50, 255]), Rgba([100, 63, 50, 255])]
+    );
+    let image = image::open(format!("{}/image_0001.png", output_dir)).unwrap().to_rgba8();
+    let pixels: Vec<Rgba<u8>> = image.pixels().cloned().collect();
+    assert_eq!(
+        pixels,
+        vec![Rgba([0, 5, 50, 255]), Rgba([100, 36, 50, 255]), Rgba([200, 0, 0, 255]), Rgba([100, 63, 50, 255])]
+    );
+}
+
+fn directory_record(name: &[u8], block: u32, size: u32, is_directory: bool, file_number: u8) -> Vec<u8> {
+    let mut record = vec![0u8; 33];
+    record[6..10].copy_from_slice(&block.to_be_bytes());
+    record[14..18].copy_from_slice(&size.to_be_bytes());
+    record[25] = if is_directory { 0x02 } else { 0x00 };
+    record[32] = name.len() as u8;
+    record.extend_from_slice(name);
+    if name.len().is_multiple_of(2) {
+        record.push(0);
+    }
+    let attributes: u16 = if is_directory { 0x8000 } else { 0x0111 };
+    record.extend_from_slice(&[0, 0, 0, 0]);
+    record.extend_from_slice(&attributes.to_be_bytes());
+    record.extend_from_slice(&[0, 0, file_number, 0]);
+    record[0] = record.len() as u8;
+    record
+}
+
+/// Builds a disc image with a root directory holding a real-time file and a subdirectory.
+fn build_disc_image(name: &str) -> String {
+    let mut label = vec![1];
+    label.extend_from_slice(b"CD-I ");
+    label.resize(8, 1);
+    label.extend_from_slice(&format!("{:<32}", "CD-RTOS").into_bytes());
+    label.extend_from_slice(&format!("{:<32}", "TESTDISC").into_bytes());
+    label.resize(84, 0);
+    label.extend_from_slice(&26u32.to_be_bytes());
+    label.resize(136, 0);
+    label.extend_from_slice(&10u32.to_be_bytes());
+    label.resize(148, 0);
+    label.extend_from_slice(&18u32.to_be_bytes());
+
+    let mut path_table = vec![1, 0];
+    path_table.extend_from_slice(&20u32.to_be_bytes());
+    path_table.extend_from_slice(&[0, 1, 0, 0]);
+
+    let mut root = directory_record(&[0], 20, 2048, true, 0);
+    root.extend(directory_record(&[1], 20, 2048, true, 0));
+    root.extend(directory_record(b"CDI", 21, 2048, true, 0));
+    root.extend(directory_record(b"INTRO.RTF", 22, 2 * 2048, false, 1));
+    let mut subdirectory = directory_record(&[0], 21, 2048, true, 0);
+    subdirectory.extend(directory_record(&[1], 20, 2048, true, 0));
+    subdirectory.extend(directory_record(b"CDI_FILE", 25, 100, false, 0));
+
+    let mut sectors: Vec<Vec<u8>> = (0..26).map(|_| build_sector(0, 0, 0, 0, &[])).collect();
+    sectors[16] = build_sector(0, 0, DATA_SUBMODE, 0, &label);
+    sectors[18] = build_sector(0, 0, DATA_SUBMODE, 0, &path_table);
+    sectors[20] = build_sector(0, 0, DATA_SUBMODE, 0, &root);
+    sectors[21] = build_sector(0, 0, DATA_SUBMODE, 0, &subdirectory);
+    sectors[22] = build_sector(1, 1, VIDEO_SUBMODE, 0x01, &[1; 8]);
+    sectors[23] = build_sector(2, 1, DATA_SUBMODE, 0, &[2; 8]);
+    sectors[24] = build_sector(1, 1, VIDEO_SUBMODE | 0x80, 0x01, &[3; 8]);
+    sectors[25] = build_sector(0, 0, DATA_SUBMODE, 0, &[4; 100]);
+    for (block, sector) in sectors.iter_mut().enumerate() {
+        set_header_address(sector, block as u32 + 150);
+    }
+    write_test_file(name, &sectors).file_name().clone()
+}
+
+#[test]
+fn test_cdi_disc() {
+    let disc = CdiDisc::new(build_disc_image("og_lib_cdi_disc.bin")).unwrap();
+    assert_eq!(disc.label().volume_identifier, "TESTDISC");
+    assert_eq!(disc.label().system_identifier, "CD-RTOS");
+    assert_eq!(disc.label().path_table_address, 18);
+
+    let entries = disc.entries().unwrap();
+    let paths: Vec<(&str, bool, u8)> = entries.iter().map(|e| (e.path.as_str(), e.is_directory, e.file_number)).collect();
+    assert_eq!(paths, vec![("/CDI", true, 0), ("/CDI/CDI_FILE", false, 0), ("/INTRO.RTF", false, 1)]);
+
+    let intro = disc.find("intro.rtf").unwrap().unwrap();
+    let sector_indexes: Vec<u32> = disc.file_sectors(&intro).iter().map(|s| s.sector_index()).collect();
+    assert_eq!(sector_indexes, vec![22, 24]);
+
+    let output = animation_path("og_lib_cdi_disc_intro.rtf");
+    assert_eq!(disc.extract_file(&intro, &output).unwrap(), 2);
+    let extracted = CdiFile::new(output);
+    assert_eq!(extracted.sector_count(), 2);
+    assert_eq!(extracted.sector(1).get_sector_data_by_type()[0], 3);
+
+    let file = disc.find("/CDI/CDI_FILE").unwrap().unwrap();
+    assert_eq!(disc.file_sectors(&file).len(), 1);
+    assert!(disc.extract_file(&entries[0], &animation_path("og_lib_cdi_disc_dir")).is_err());
+}
+
+#[test]
+fn test_cdi_command_line() {
+    let run = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_cdi")).args(args).output().unwrap();
+        (output.status.success(), String::from_utf8_lossy(&output.stdout).to_string())
+    };
+    let image = build_disc_image("og_lib_cdi_command_line.bin");
+
+    let (success, output) = run(&["sectors", &image]);
+    assert!(success);
+    assert_eq!(output.lines().count(), 27);
+    assert!(output.lines().nth(23).unwrap().contains("V"));
+    assert!(output.contains("CLUT7 Normal"));
+
+    let (success, output) = run(&["info", &image]);
+    assert!(success);
+    assert!(output.contains("sectors: 26"));
+
+    let (success, output) = run(&["disc", "ls", &image]);
+    assert!(success);
+    assert!(output.starts_with("TESTDISC (CD-RTOS)"));
+    assert!(output.contains("/CDI/CDI_FILE"));
+
+    let extracted = animation_path("og_lib_cdi_command_line.rtf");
+    let (success, _) = run(&["disc", "extract", &image, "/INTRO.RTF", &extracted]);
+    assert!(success);
+    assert_eq!(std::fs::metadata(&extracted).unwrap().len(), 2 * 2352);
+
+    assert!(!run(&["info", "/nonexistent/file.rtf"]).0);
+    assert!(!run(&["unknown"]).0);
+
+    // Truncated files are rejected when shorter than a sector, and lose their partial sector
+    let truncated = animation_path("og_lib_cdi_command_line_short.rtf");
+    std::fs::write(&truncated, [0u8; 10]).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_cdi")).args(["sectors", &truncated]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("shorter than one 2352 byte sector"));
+    std::fs::write(&truncated, [0u8; 3000]).unwrap();
+    let (success, output) = run(&["info", &truncated]);
+    assert!(success);
+    assert!(output.contains("sectors: 1"));
+    assert!(!run(&["disc", "ls", &truncated]).0);
+}
+
+#[test]
+fn test_video_records_split_on_trigger() {
+    const TRIGGER: u8 = 1 << 4;
+    let sectors = vec![
+        build_sector(1, 1, VIDEO_SUBMODE, 0x04, &[1]),
+        build_sector(1, 2, VIDEO_SUBMODE, 0x04, &[9]),
+        build_sector(1, 1, VIDEO_SUBMODE | TRIGGER, 0x04, &[2]),
+        build_sector(1, 1, VIDEO_SUBMODE, 0x04, &[3]),
+        build_sector(1, 1, VIDEO_SUBMODE | TRIGGER, 0x04, &[4]),
+        build_sector(1, 1, VIDEO_SUBMODE, 0x04, &[5]),
+    ];
+    let file = write_test_file("og_lib_cdi_trigger_records.rtf", &sectors);
+    let records: Vec<Vec<u32>> = file
+        .get_video_records()
+        .iter()
+        .map(|r| r.iter().map(|s| s.sector_index()).collect())
+        .collect();
+    assert_eq!(records, vec![vec![0, 2], vec![3, 4], vec![1], vec![5]]);
+
+    let timings = frame_timings(&file.get_video_records()[..2], 1);
+    assert_eq!(timings.iter().map(|t| t.sector_index).collect::<Vec<_>>(), vec![2, 4]);
+}
+